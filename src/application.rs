@@ -1,71 +1,456 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::ffi::OsStr;
+use std::fmt;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
 use rayon::prelude::*;
+use sha2::{Digest, Sha256};
 
-use crate::domain::{ExtractStats, MODEL_INFO_FILE_NAME};
+use crate::domain::{
+    CatalogModel, CatalogRecord, CatalogSource, DirectoryFilter, ErrorPolicy, ExtractOptions, ExtractPlan, ExtractReport,
+    ExtractStats, ModelInfoSchema, PlannedArchive, PlannedEntry, SchemaViolation,
+    TrainingMetadata, TrainingRecord, GGUF_EXTENSION, MODEL_INFO_FILE_NAME,
+    SAFETENSORS_EXTENSION,
+};
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
 
 #[derive(Debug, thiserror::Error)]
 pub enum ExtractError {
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
-    #[error("{0}")]
-    Message(String),
+    #[error("invalid entry pattern: {0}")]
+    Pattern(#[from] globset::Error),
+    #[error("cannot read directory {}: {source}", path.display())]
+    ReadDirectory {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("cannot read {}: {source}", path.display())]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("cannot write {}: {source}", path.display())]
+    Write {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("{} is not under {}", path.display(), root.display())]
+    OutsideRoot { path: PathBuf, root: PathBuf },
+    #[error("invalid schema {}: {source}", path.display())]
+    Schema { path: PathBuf, source: BoxError },
+    #[error("catalog database {}: {source}", path.display())]
+    Catalog { path: PathBuf, source: BoxError },
+    #[error("cannot watch {}: {source}", path.display())]
+    Watch { path: PathBuf, source: BoxError },
+}
+
+impl ExtractError {
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::Io(_) | Self::Pattern(_) => None,
+            Self::ReadDirectory { path, .. }
+            | Self::Read { path, .. }
+            | Self::Write { path, .. }
+            | Self::OutsideRoot { path, .. }
+            | Self::Schema { path, .. }
+            | Self::Catalog { path, .. }
+            | Self::Watch { path, .. } => Some(path),
+        }
+    }
+
+    // 出力側の問題 (ディスクの空き不足や書き込み禁止の出力先など)。アーカイブや読み取りの問題と違い、
+    // 実行を続けるかは ErrorPolicy に従い、続ける場合は write_failures として数える
+    pub fn is_output(&self) -> bool {
+        matches!(self, Self::Write { .. })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidArchiveKind {
+    Unsupported,
+    Unreadable,
+    Corrupt,
+    Encrypted,
+    Entry,
+}
+
+impl InvalidArchiveKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Unsupported => "unsupported format",
+            Self::Unreadable => "cannot open",
+            Self::Corrupt => "corrupt archive",
+            Self::Encrypted => "encrypted entry",
+            Self::Entry => "cannot read entry",
+        }
+    }
+}
+
+// アーカイブ側の問題。出力側の失敗は ExtractError::Write として呼び出し元に返し、ここには含めない
+#[derive(Debug, Clone)]
+pub struct InvalidArchive {
+    pub path: PathBuf,
+    pub kind: InvalidArchiveKind,
+    pub entry: Option<String>,
+    pub source: Arc<dyn std::error::Error + Send + Sync>,
+}
+
+impl InvalidArchive {
+    pub fn new(path: &Path, kind: InvalidArchiveKind, source: impl Into<BoxError>) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            kind,
+            entry: None,
+            source: Arc::from(source.into()),
+        }
+    }
+
+    pub fn with_entry(mut self, entry: &str) -> Self {
+        self.entry = Some(entry.to_string());
+        self
+    }
+}
+
+impl fmt::Display for InvalidArchive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.entry {
+            Some(entry) => write!(f, "{} {}: {}", self.kind.as_str(), entry, self.source),
+            None => write!(f, "{}: {}", self.kind.as_str(), self.source),
+        }
+    }
+}
+
+impl std::error::Error for InvalidArchive {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
 }
 
+// 元のエラーは比較できないので、表示される内容が同じなら同じ失敗とみなす
+impl PartialEq for InvalidArchive {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+            && self.kind == other.kind
+            && self.entry == other.entry
+            && self.source.to_string() == other.source.to_string()
+    }
+}
+
+impl Eq for InvalidArchive {}
+
 pub trait FilePorts: Send + Sync {
     fn for_each_directory(
         &self,
         root: &Path,
+        filter: &DirectoryFilter,
         on_dir: &mut dyn FnMut(PathBuf) -> Result<(), ExtractError>,
+        on_error: &mut dyn FnMut(PathBuf, ExtractError) -> Result<(), ExtractError>,
     ) -> Result<(), ExtractError>;
     fn list_files_in_dir(&self, dir: &Path) -> Result<Vec<PathBuf>, ExtractError>;
+    fn is_archive(&self, path: &Path) -> bool;
+    fn is_file(&self, path: &Path) -> bool;
     fn extract_zip_entry_if_exists(
         &self,
         zip_path: &Path,
-        entry_name: &str,
+        options: &ExtractOptions,
+        output_dir: &Path,
+    ) -> Result<Vec<ZipEntryOutcome>, ExtractError>;
+    fn generate_model_info_from_header(
+        &self,
+        model_path: &Path,
+        output_dir: &Path,
+    ) -> Result<HeaderOutcome, ExtractError>;
+    fn read_model_metadata(&self, model_path: &Path) -> Result<HeaderMetadata, ExtractError>;
+    fn write_training_sidecar(
+        &self,
+        model_path: &Path,
         output_dir: &Path,
-    ) -> Result<ZipEntryOutcome, ExtractError>;
+        metadata: &TrainingMetadata,
+    ) -> Result<PathBuf, ExtractError>;
+    fn write_gguf_metadata(
+        &self,
+        model_path: &Path,
+        output_dir: &Path,
+    ) -> Result<HeaderOutcome, ExtractError>;
+    fn read_file(&self, path: &Path) -> Result<Vec<u8>, ExtractError>;
+    fn file_size(&self, path: &Path) -> Result<u64, ExtractError>;
+}
+
+// 進捗表示と同じタイミングで、ファイル単位の詳細を受け取りたい出力先 (カタログやデータベース) のための口
+pub trait CatalogSink: Send + Sync {
+    fn on_start(&self, _root: &Path) -> Result<(), ExtractError> {
+        Ok(())
+    }
+
+    // 前回の実行結果を覚えている出力先 (状態キャッシュ) は、変わっていないアーカイブを開かずに済ませられる
+    fn is_unchanged(&self, _zip_path: &Path) -> Result<bool, ExtractError> {
+        Ok(false)
+    }
+
+    fn on_archive(&self, _record: &ArchiveRecord) -> Result<(), ExtractError> {
+        Ok(())
+    }
+
+    fn on_record(&self, _record: &CatalogRecord) -> Result<(), ExtractError> {
+        Ok(())
+    }
+
+    fn on_finish(&self, _stats: &ExtractStats) -> Result<(), ExtractError> {
+        Ok(())
+    }
+}
+
+impl CatalogSink for Vec<&dyn CatalogSink> {
+    fn on_start(&self, root: &Path) -> Result<(), ExtractError> {
+        self.iter().try_for_each(|sink| sink.on_start(root))
+    }
+
+    fn is_unchanged(&self, zip_path: &Path) -> Result<bool, ExtractError> {
+        for sink in self {
+            if sink.is_unchanged(zip_path)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn on_archive(&self, record: &ArchiveRecord) -> Result<(), ExtractError> {
+        self.iter().try_for_each(|sink| sink.on_archive(record))
+    }
+
+    fn on_record(&self, record: &CatalogRecord) -> Result<(), ExtractError> {
+        self.iter().try_for_each(|sink| sink.on_record(record))
+    }
+
+    fn on_finish(&self, stats: &ExtractStats) -> Result<(), ExtractError> {
+        self.iter().try_for_each(|sink| sink.on_finish(stats))
+    }
 }
 
 pub trait ProgressReporter: Send + Sync {
     fn on_start(&self, root: &Path);
     fn on_update(&self, stats: &ExtractStats);
-    fn on_invalid_zip(&self, zip_path: &Path, reason: &str);
+    fn on_invalid_zip(&self, zip_path: &Path, error: &InvalidArchive);
+    fn on_password_required(&self, zip_path: &Path, error: &InvalidArchive);
+    fn on_invalid_header(&self, model_path: &Path, reason: &str);
+    fn on_invalid_json(&self, zip_path: &Path, target_path: &Path, reason: &str);
+    fn on_entry_too_large(&self, zip_path: &Path, target_path: &Path, reason: &str);
+    fn on_schema_violation(&self, path: &Path, violations: &[SchemaViolation]);
+    fn on_error(&self, path: &Path, error: &ExtractError);
     fn on_finish(&self, stats: &ExtractStats);
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ZipEntryOutcome {
-    Extracted,
+    Extracted {
+        pattern: String,
+        path: PathBuf,
+        backup: Option<PathBuf>,
+        bytes: u64,
+        sha256: String,
+    },
+    Skipped {
+        pattern: String,
+        path: PathBuf,
+        reason: SkipReason,
+    },
+    InvalidJson {
+        pattern: String,
+        path: PathBuf,
+        reason: String,
+    },
+    // 大きさか圧縮率の制限を超えたので途中で展開をやめた。既存ファイルはそのまま残る
+    TooLarge {
+        pattern: String,
+        path: PathBuf,
+        reason: String,
+    },
+    Planned(PlannedEntry),
     NotFound,
-    InvalidZip(String),
+    InvalidZip(InvalidArchive),
+    // 暗号化されていて、渡されたパスワードがどれも合わなかった(または一つも渡されなかった)
+    PasswordRequired(InvalidArchive),
+    // 出力先に書き込めなかったので、このアーカイブの展開を諦めた
+    WriteFailed {
+        path: PathBuf,
+        reason: String,
+    },
+    // 前回から変わっていないので、状態キャッシュに従って開かなかった
+    Cached,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveRecord {
+    pub path: PathBuf,
+    pub directory: PathBuf,
+    pub outcomes: Vec<ZipEntryOutcome>,
+    pub duration: Duration,
+}
+
+impl ArchiveRecord {
+    // アーカイブ単位の結果は、最も重要なものを一つだけ代表として返す
+    pub fn status(&self) -> &'static str {
+        let has = |matches: fn(&ZipEntryOutcome) -> bool| self.outcomes.iter().any(matches);
+
+        if has(|outcome| matches!(outcome, ZipEntryOutcome::InvalidZip(_))) {
+            "invalid"
+        } else if has(|outcome| matches!(outcome, ZipEntryOutcome::PasswordRequired(_))) {
+            "password_required"
+        } else if has(|outcome| matches!(outcome, ZipEntryOutcome::WriteFailed { .. })) {
+            "write_failed"
+        } else if has(|outcome| matches!(outcome, ZipEntryOutcome::Extracted { .. })) {
+            "extracted"
+        } else if has(|outcome| matches!(outcome, ZipEntryOutcome::InvalidJson { .. })) {
+            "invalid_json"
+        } else if has(|outcome| matches!(outcome, ZipEntryOutcome::TooLarge { .. })) {
+            "too_large"
+        } else if has(|outcome| matches!(outcome, ZipEntryOutcome::Skipped { .. })) {
+            "skipped"
+        } else if has(|outcome| matches!(outcome, ZipEntryOutcome::Planned(_))) {
+            "planned"
+        } else if has(|outcome| matches!(outcome, ZipEntryOutcome::Cached)) {
+            "cached"
+        } else {
+            "not_found"
+        }
+    }
+
+    pub fn reason(&self) -> Option<String> {
+        self.outcomes.iter().find_map(|outcome| match outcome {
+            ZipEntryOutcome::InvalidZip(error) | ZipEntryOutcome::PasswordRequired(error) => {
+                Some(error.to_string())
+            }
+            ZipEntryOutcome::InvalidJson { reason, .. }
+            | ZipEntryOutcome::TooLarge { reason, .. }
+            | ZipEntryOutcome::WriteFailed { reason, .. } => Some(reason.clone()),
+            _ => None,
+        })
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.outcomes
+            .iter()
+            .map(|outcome| match outcome {
+                ZipEntryOutcome::Extracted { bytes, .. } => *bytes,
+                _ => 0,
+            })
+            .sum()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    Exists,
+    Unchanged,
+    NotNewer,
+}
+
+impl SkipReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Exists => "exists",
+            Self::Unchanged => "unchanged",
+            Self::NotNewer => "not newer",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderOutcome {
+    Generated(PathBuf),
+    NoMetadata,
+    InvalidHeader(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderMetadata {
+    Found(BTreeMap<String, String>),
+    NoMetadata,
+    InvalidHeader(String),
 }
 
 struct AtomicExtractStats {
     directories_scanned: AtomicU64,
-    safetensors_directories: AtomicU64,
+    model_directories: AtomicU64,
+    model_directories_by_extension: Mutex<BTreeMap<String, u64>>,
     zip_files_checked: AtomicU64,
+    invalid_zips: AtomicU64,
+    password_required: AtomicU64,
     extracted: AtomicU64,
+    extracted_by_entry: Mutex<BTreeMap<String, u64>>,
+    invalid_json: AtomicU64,
+    entries_too_large: AtomicU64,
+    invalid_headers: AtomicU64,
+    unreadable_directories: AtomicU64,
+    write_failures: AtomicU64,
+    skipped_existing: AtomicU64,
+    skipped_unchanged: AtomicU64,
+    skipped_not_newer: AtomicU64,
+    skipped_cached: AtomicU64,
+    backed_up: AtomicU64,
+    schema_violations: AtomicU64,
+    generated_from_header: AtomicU64,
+    training_reports: AtomicU64,
+    gguf_metadata: AtomicU64,
 }
 
 impl AtomicExtractStats {
     fn new() -> Self {
         Self {
             directories_scanned: AtomicU64::new(0),
-            safetensors_directories: AtomicU64::new(0),
+            model_directories: AtomicU64::new(0),
+            model_directories_by_extension: Mutex::new(BTreeMap::new()),
             zip_files_checked: AtomicU64::new(0),
+            invalid_zips: AtomicU64::new(0),
+            password_required: AtomicU64::new(0),
             extracted: AtomicU64::new(0),
+            extracted_by_entry: Mutex::new(BTreeMap::new()),
+            invalid_json: AtomicU64::new(0),
+            entries_too_large: AtomicU64::new(0),
+            invalid_headers: AtomicU64::new(0),
+            unreadable_directories: AtomicU64::new(0),
+            write_failures: AtomicU64::new(0),
+            skipped_existing: AtomicU64::new(0),
+            skipped_unchanged: AtomicU64::new(0),
+            skipped_not_newer: AtomicU64::new(0),
+            skipped_cached: AtomicU64::new(0),
+            backed_up: AtomicU64::new(0),
+            schema_violations: AtomicU64::new(0),
+            generated_from_header: AtomicU64::new(0),
+            training_reports: AtomicU64::new(0),
+            gguf_metadata: AtomicU64::new(0),
         }
     }
 
     fn snapshot(&self) -> ExtractStats {
         ExtractStats {
             directories_scanned: self.directories_scanned.load(Ordering::Relaxed),
-            safetensors_directories: self.safetensors_directories.load(Ordering::Relaxed),
+            model_directories: self.model_directories.load(Ordering::Relaxed),
+            model_directories_by_extension: lock(&self.model_directories_by_extension).clone(),
             zip_files_checked: self.zip_files_checked.load(Ordering::Relaxed),
+            invalid_zips: self.invalid_zips.load(Ordering::Relaxed),
+            password_required: self.password_required.load(Ordering::Relaxed),
             extracted: self.extracted.load(Ordering::Relaxed),
+            extracted_by_entry: lock(&self.extracted_by_entry).clone(),
+            invalid_json: self.invalid_json.load(Ordering::Relaxed),
+            entries_too_large: self.entries_too_large.load(Ordering::Relaxed),
+            invalid_headers: self.invalid_headers.load(Ordering::Relaxed),
+            unreadable_directories: self.unreadable_directories.load(Ordering::Relaxed),
+            write_failures: self.write_failures.load(Ordering::Relaxed),
+            skipped_existing: self.skipped_existing.load(Ordering::Relaxed),
+            skipped_unchanged: self.skipped_unchanged.load(Ordering::Relaxed),
+            skipped_not_newer: self.skipped_not_newer.load(Ordering::Relaxed),
+            skipped_cached: self.skipped_cached.load(Ordering::Relaxed),
+            backed_up: self.backed_up.load(Ordering::Relaxed),
+            schema_violations: self.schema_violations.load(Ordering::Relaxed),
+            generated_from_header: self.generated_from_header.load(Ordering::Relaxed),
+            training_reports: self.training_reports.load(Ordering::Relaxed),
+            gguf_metadata: self.gguf_metadata.load(Ordering::Relaxed),
         }
     }
 
@@ -73,16 +458,91 @@ impl AtomicExtractStats {
         self.directories_scanned.fetch_add(1, Ordering::Relaxed);
     }
 
-    fn increment_safetensors_directories(&self) {
-        self.safetensors_directories.fetch_add(1, Ordering::Relaxed);
+    fn increment_model_directories(&self, extensions: &BTreeSet<&str>) {
+        self.model_directories.fetch_add(1, Ordering::Relaxed);
+        let mut by_extension = lock(&self.model_directories_by_extension);
+        for extension in extensions {
+            *by_extension.entry(extension.to_string()).or_insert(0) += 1;
+        }
     }
 
     fn increment_zip_files_checked(&self) {
         self.zip_files_checked.fetch_add(1, Ordering::Relaxed);
     }
 
-    fn increment_extracted(&self) {
+    fn increment_invalid_zips(&self) {
+        self.invalid_zips.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn increment_password_required(&self) {
+        self.password_required.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn increment_extracted(&self, pattern: &str) {
         self.extracted.fetch_add(1, Ordering::Relaxed);
+        *lock(&self.extracted_by_entry)
+            .entry(pattern.to_string())
+            .or_insert(0) += 1;
+    }
+
+    fn increment_invalid_json(&self) {
+        self.invalid_json.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn increment_entries_too_large(&self) {
+        self.entries_too_large.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn increment_invalid_headers(&self) {
+        self.invalid_headers.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn increment_unreadable_directories(&self) {
+        self.unreadable_directories.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn increment_write_failures(&self) {
+        self.write_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn increment_skipped(&self, reason: SkipReason) {
+        let counter = match reason {
+            SkipReason::Exists => &self.skipped_existing,
+            SkipReason::Unchanged => &self.skipped_unchanged,
+            SkipReason::NotNewer => &self.skipped_not_newer,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn increment_skipped_cached(&self) {
+        self.skipped_cached.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn increment_backed_up(&self) {
+        self.backed_up.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn increment_schema_violations(&self) {
+        self.schema_violations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn increment_generated_from_header(&self) {
+        self.generated_from_header.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn increment_training_reports(&self) {
+        self.training_reports.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn increment_gguf_metadata(&self) {
+        self.gguf_metadata.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(err) => err.into_inner(),
     }
 }
 
@@ -91,62 +551,341 @@ pub fn extract_model_info(
     progress: &dyn ProgressReporter,
     root: &Path,
 ) -> Result<ExtractStats, ExtractError> {
-    let stats = AtomicExtractStats::new();
+    extract_model_info_with_options(ports, progress, root, &ExtractOptions::default())
+        .map(|report| report.stats)
+}
 
+pub fn extract_model_info_with_options(
+    ports: &dyn FilePorts,
+    progress: &dyn ProgressReporter,
+    root: &Path,
+    options: &ExtractOptions,
+) -> Result<ExtractReport, ExtractError> {
+    extract_model_info_with_catalog(ports, progress, root, options, None)
+}
+
+pub fn extract_model_info_with_catalog(
+    ports: &dyn FilePorts,
+    progress: &dyn ProgressReporter,
+    root: &Path,
+    options: &ExtractOptions,
+    catalog: Option<&dyn CatalogSink>,
+) -> Result<ExtractReport, ExtractError> {
     progress.on_start(root);
+    if let Some(catalog) = catalog.filter(|_| !options.dry_run) {
+        catalog.on_start(root)?;
+    }
 
+    let stats = AtomicExtractStats::new();
     let mut directories = Vec::new();
-    ports.for_each_directory(root, &mut |dir_path| {
-        directories.push(dir_path);
-        Ok::<(), ExtractError>(())
-    })?;
+    ports.for_each_directory(
+        root,
+        &options.directories,
+        &mut |dir_path| {
+            directories.push(dir_path);
+            Ok::<(), ExtractError>(())
+        },
+        &mut |path, err| report_error(progress, &stats, options, &path, err),
+    )?;
+
+    process_directories(ports, progress, root, &directories, options, catalog, stats)
+}
+
+// ツリー全体を歩かずに、変更のあったディレクトリだけを処理し直すための入口 (watch モード用)
+pub fn extract_model_info_in_directories(
+    ports: &dyn FilePorts,
+    progress: &dyn ProgressReporter,
+    root: &Path,
+    directories: &[PathBuf],
+    options: &ExtractOptions,
+    catalog: Option<&dyn CatalogSink>,
+) -> Result<ExtractReport, ExtractError> {
+    progress.on_start(root);
+    if let Some(catalog) = catalog.filter(|_| !options.dry_run) {
+        catalog.on_start(root)?;
+    }
+
+    let stats = AtomicExtractStats::new();
+    process_directories(ports, progress, root, directories, options, catalog, stats)
+}
+
+// 読めないディレクトリや書き込めない出力先は、方針が Continue なら報告と集計だけして残りの処理を続ける
+fn report_error(
+    progress: &dyn ProgressReporter,
+    stats: &AtomicExtractStats,
+    options: &ExtractOptions,
+    path: &Path,
+    err: ExtractError,
+) -> Result<(), ExtractError> {
+    match options.on_error {
+        ErrorPolicy::FailFast => Err(err),
+        ErrorPolicy::Continue => {
+            if err.is_output() {
+                stats.increment_write_failures();
+            } else {
+                stats.increment_unreadable_directories();
+            }
+            progress.on_error(path, &err);
+            Ok(())
+        }
+    }
+}
+
+// 書き込めない出力先は他のファイルには関係しないので、方針に従って報告し、そのファイルだけを諦める。
+// 読み取り側やカタログの失敗はこれまでどおり呼び出し元に返す
+fn skip_output_error<T>(
+    progress: &dyn ProgressReporter,
+    stats: &AtomicExtractStats,
+    options: &ExtractOptions,
+    source: &Path,
+    result: Result<T, ExtractError>,
+) -> Result<Option<T>, ExtractError> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(err) if err.is_output() => {
+            let path = err.path().unwrap_or(source).to_path_buf();
+            report_error(progress, stats, options, &path, err)?;
+            Ok(None)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+fn process_directories(
+    ports: &dyn FilePorts,
+    progress: &dyn ProgressReporter,
+    root: &Path,
+    directories: &[PathBuf],
+    options: &ExtractOptions,
+    catalog: Option<&dyn CatalogSink>,
+    stats: AtomicExtractStats,
+) -> Result<ExtractReport, ExtractError> {
+    let training = Mutex::new(Vec::new());
+    let planned = Mutex::new(Vec::new());
 
     directories.par_iter().try_for_each(|dir_path| {
         stats.increment_directories();
 
-        let files = ports.list_files_in_dir(dir_path)?;
-        let mut has_safetensors = false;
+        let output_dir = output_dir_for(root, dir_path, options)?;
+        let files = match ports.list_files_in_dir(dir_path) {
+            Ok(files) => files,
+            Err(err) => return report_error(progress, &stats, options, dir_path, err),
+        };
+        let mut model_extensions = BTreeSet::new();
+        let mut model_files = Vec::new();
+        let mut safetensors_files = Vec::new();
+        let mut gguf_files = Vec::new();
         let mut zip_files = Vec::new();
+        // 既にある model_info.json は、読み込み元ではなく書き出す先で確かめる
+        let existing_path = output_dir.join(MODEL_INFO_FILE_NAME);
+        let existing_model_info = ports.is_file(&existing_path).then_some(existing_path);
+        let mut has_model_info = existing_model_info.is_some();
+        let mut extracted_model_info = None;
+        let mut model_info_archive = None;
 
         for file in files {
-            match file.extension() {
-                Some(ext) if ext == OsStr::new("safetensors") => {
-                    has_safetensors = true;
-                }
-                Some(ext) if ext == OsStr::new("zip") => {
-                    zip_files.push(file);
+            if file.extension() == Some(OsStr::new(SAFETENSORS_EXTENSION)) {
+                safetensors_files.push(file.clone());
+            }
+            if file.extension() == Some(OsStr::new(GGUF_EXTENSION)) {
+                gguf_files.push(file.clone());
+            }
+
+            let model_extension = file
+                .extension()
+                .and_then(OsStr::to_str)
+                .and_then(|ext| options.model_extensions.find_match(ext));
+
+            if let Some(model_extension) = model_extension {
+                model_extensions.insert(model_extension);
+                model_files.push(file);
+            } else if ports.is_archive(&file) {
+                zip_files.push(file);
+            }
+        }
+
+        // GGUF はトリガー拡張子の設定とは関係なく、見つかったディレクトリすべてで処理する
+        if options.gguf_metadata && !options.dry_run {
+            for model_path in &gguf_files {
+                let written = ports.write_gguf_metadata(model_path, &output_dir);
+                let outcome = skip_output_error(progress, &stats, options, model_path, written)?;
+                let Some(outcome) = outcome else { continue };
+                match outcome {
+                    HeaderOutcome::Generated(_) => {
+                        stats.increment_gguf_metadata();
+                    }
+                    HeaderOutcome::InvalidHeader(reason) => {
+                        stats.increment_invalid_headers();
+                        progress.on_invalid_header(model_path, &reason);
+                    }
+                    HeaderOutcome::NoMetadata => {}
                 }
-                _ => {}
             }
         }
 
-        if has_safetensors {
-            stats.increment_safetensors_directories();
+        if !model_extensions.is_empty() {
+            stats.increment_model_directories(&model_extensions);
             let snapshot = stats.snapshot();
             progress.on_update(&snapshot);
 
             for zip_path in zip_files {
+                if let Some(catalog) = catalog.filter(|_| !options.dry_run)
+                    && catalog.is_unchanged(&zip_path)?
+                {
+                    stats.increment_skipped_cached();
+                    // 開かなかったアーカイブも、調べた結果としてレポートに残す
+                    catalog.on_archive(&ArchiveRecord {
+                        path: zip_path.clone(),
+                        directory: dir_path.clone(),
+                        outcomes: vec![ZipEntryOutcome::Cached],
+                        duration: Duration::ZERO,
+                    })?;
+                    continue;
+                }
                 stats.increment_zip_files_checked();
 
-                let outcome = ports.extract_zip_entry_if_exists(
-                    &zip_path,
-                    MODEL_INFO_FILE_NAME,
-                    dir_path,
-                )?;
-
-                match outcome {
-                    ZipEntryOutcome::Extracted => {
-                        stats.increment_extracted();
+                let started = Instant::now();
+                // 書き込めなかったアーカイブも、方針に従って報告したうえで結果の一つとして記録する
+                let extracted = ports.extract_zip_entry_if_exists(&zip_path, options, &output_dir);
+                let outcomes = match extracted {
+                    Ok(outcomes) => outcomes,
+                    Err(err) if err.is_output() => {
+                        let path = err.path().unwrap_or(&zip_path).to_path_buf();
+                        let reason = err.to_string();
+                        report_error(progress, &stats, options, &path, err)?;
+                        vec![ZipEntryOutcome::WriteFailed { path, reason }]
                     }
-                    ZipEntryOutcome::InvalidZip(reason) => {
-                        progress.on_invalid_zip(&zip_path, &reason);
+                    Err(err) => return Err(err),
+                };
+                let duration = started.elapsed();
+                let mut plan = PlannedArchive {
+                    path: zip_path.clone(),
+                    entries: Vec::new(),
+                    invalid: None,
+                };
+
+                for outcome in &outcomes {
+                    match outcome {
+                        ZipEntryOutcome::Extracted {
+                            pattern,
+                            path,
+                            backup,
+                            ..
+                        } => {
+                            if path.file_name() == Some(OsStr::new(MODEL_INFO_FILE_NAME)) {
+                                has_model_info = true;
+                                extracted_model_info = Some(path.clone());
+                                model_info_archive = Some(zip_path.clone());
+                            }
+                            if backup.is_some() {
+                                stats.increment_backed_up();
+                            }
+                            stats.increment_extracted(pattern);
+                        }
+                        ZipEntryOutcome::Skipped { path, reason, .. } => {
+                            if path.file_name() == Some(OsStr::new(MODEL_INFO_FILE_NAME)) {
+                                has_model_info = true;
+                            }
+                            stats.increment_skipped(*reason);
+                        }
+                        ZipEntryOutcome::InvalidJson { path, reason, .. } => {
+                            stats.increment_invalid_json();
+                            progress.on_invalid_json(&zip_path, path, reason);
+                        }
+                        ZipEntryOutcome::TooLarge { path, reason, .. } => {
+                            stats.increment_entries_too_large();
+                            progress.on_entry_too_large(&zip_path, path, reason);
+                        }
+                        ZipEntryOutcome::Planned(entry) => {
+                            plan.entries.push(entry.clone());
+                        }
+                        ZipEntryOutcome::InvalidZip(error) => {
+                            stats.increment_invalid_zips();
+                            progress.on_invalid_zip(&zip_path, error);
+                            plan.invalid = Some(error.to_string());
+                        }
+                        ZipEntryOutcome::PasswordRequired(error) => {
+                            stats.increment_password_required();
+                            progress.on_password_required(&zip_path, error);
+                            plan.invalid = Some(error.to_string());
+                        }
+                        ZipEntryOutcome::WriteFailed { reason, .. } => {
+                            plan.invalid = Some(reason.clone());
+                        }
+                        ZipEntryOutcome::NotFound | ZipEntryOutcome::Cached => {}
                     }
-                    ZipEntryOutcome::NotFound => {}
+                }
+
+                if options.dry_run {
+                    lock(&planned).push(plan);
+                } else if let Some(catalog) = catalog {
+                    catalog.on_archive(&ArchiveRecord {
+                        path: zip_path.clone(),
+                        directory: dir_path.clone(),
+                        outcomes,
+                        duration,
+                    })?;
                 }
 
                 let snapshot = stats.snapshot();
                 progress.on_update(&snapshot);
             }
+
+            // ドライランではディスクに書き込む処理をすべて飛ばし、展開計画だけを返す
+            if options.dry_run {
+                return Ok(());
+            }
+
+            if let Some(schema) = &options.schema {
+                let target = match &extracted_model_info {
+                    Some(path) => Some(path.clone()),
+                    None if options.validate_existing => existing_model_info.clone(),
+                    None => None,
+                };
+                if let Some(path) = target {
+                    validate_model_info(ports, progress, &stats, schema, &path)?;
+                }
+            }
+
+            if options.training_report {
+                for model_path in &safetensors_files {
+                    let reported =
+                        report_training(ports, progress, &stats, model_path, &output_dir);
+                    let record =
+                        skip_output_error(progress, &stats, options, model_path, reported)?;
+                    if let Some(record) = record.flatten() {
+                        stats.increment_training_reports();
+                        lock(&training).push(record);
+                    }
+                }
+            }
+
+            let mut generated_model_info = None;
+            if options.header_fallback && !has_model_info {
+                let generated =
+                    generate_from_headers(ports, progress, &stats, &output_dir, safetensors_files);
+                generated_model_info =
+                    skip_output_error(progress, &stats, options, dir_path, generated)?.flatten();
+            }
+
+            if let Some(catalog) = catalog {
+                let model_info = match (extracted_model_info, generated_model_info) {
+                    (Some(path), _) => Some((path, CatalogSource::Extracted)),
+                    (None, Some(path)) => Some((path, CatalogSource::Generated)),
+                    (None, None) => existing_model_info.map(|path| (path, CatalogSource::Existing)),
+                };
+                if let Some((model_info_path, source)) = model_info {
+                    let record = catalog_record(
+                        ports,
+                        dir_path,
+                        model_info_path,
+                        source,
+                        model_info_archive,
+                        model_files,
+                    )?;
+                    catalog.on_record(&record)?;
+                }
+            }
         } else {
             let snapshot = stats.snapshot();
             progress.on_update(&snapshot);
@@ -157,6 +896,164 @@ pub fn extract_model_info(
 
     let final_stats = stats.snapshot();
     progress.on_finish(&final_stats);
+    if let Some(catalog) = catalog.filter(|_| !options.dry_run) {
+        catalog.on_finish(&final_stats)?;
+    }
+
+    let mut training = training.into_inner().unwrap_or_else(|err| err.into_inner());
+    training.sort_by(|a, b| a.model_path.cmp(&b.model_path));
+
+    let plan = options.dry_run.then(|| {
+        let mut archives = planned.into_inner().unwrap_or_else(|err| err.into_inner());
+        archives.sort_by(|a, b| a.path.cmp(&b.path));
+        ExtractPlan { archives }
+    });
+
+    Ok(ExtractReport {
+        stats: final_stats,
+        training,
+        plan,
+    })
+}
+
+// 出力先が別ツリーの場合は走査ルートからの相対パスをそのまま再現する
+fn output_dir_for(
+    root: &Path,
+    dir_path: &Path,
+    options: &ExtractOptions,
+) -> Result<PathBuf, ExtractError> {
+    let Some(output_root) = &options.output_root else {
+        return Ok(dir_path.to_path_buf());
+    };
+
+    let relative = dir_path
+        .strip_prefix(root)
+        .map_err(|_| ExtractError::OutsideRoot {
+            path: dir_path.to_path_buf(),
+            root: root.to_path_buf(),
+        })?;
+    Ok(output_root.join(relative))
+}
+
+fn validate_model_info(
+    ports: &dyn FilePorts,
+    progress: &dyn ProgressReporter,
+    stats: &AtomicExtractStats,
+    schema: &ModelInfoSchema,
+    path: &Path,
+) -> Result<(), ExtractError> {
+    let contents = ports.read_file(path)?;
+    let violations = match serde_json::from_slice::<serde_json::Value>(&contents) {
+        Ok(instance) => schema.validate(&instance),
+        Err(err) => vec![SchemaViolation {
+            pointer: String::new(),
+            message: format!("not valid JSON: {err}"),
+        }],
+    };
+
+    if !violations.is_empty() {
+        stats.increment_schema_violations();
+        progress.on_schema_violation(path, &violations);
+    }
+
+    Ok(())
+}
+
+fn report_training(
+    ports: &dyn FilePorts,
+    progress: &dyn ProgressReporter,
+    stats: &AtomicExtractStats,
+    model_path: &Path,
+    output_dir: &Path,
+) -> Result<Option<TrainingRecord>, ExtractError> {
+    let metadata = match ports.read_model_metadata(model_path)? {
+        HeaderMetadata::Found(metadata) => metadata,
+        HeaderMetadata::NoMetadata => return Ok(None),
+        HeaderMetadata::InvalidHeader(reason) => {
+            stats.increment_invalid_headers();
+            progress.on_invalid_header(model_path, &reason);
+            return Ok(None);
+        }
+    };
+    let Some(metadata) = TrainingMetadata::from_metadata(&metadata) else {
+        return Ok(None);
+    };
+
+    let sidecar_path = ports.write_training_sidecar(model_path, output_dir, &metadata)?;
+
+    Ok(Some(TrainingRecord {
+        model_path: model_path.to_path_buf(),
+        sidecar_path,
+        metadata,
+    }))
+}
+
+fn catalog_record(
+    ports: &dyn FilePorts,
+    dir_path: &Path,
+    model_info_path: PathBuf,
+    source: CatalogSource,
+    source_archive: Option<PathBuf>,
+    mut model_files: Vec<PathBuf>,
+) -> Result<CatalogRecord, ExtractError> {
+    model_files.sort();
+    let models = model_files
+        .iter()
+        .map(|path| {
+            Ok(CatalogModel {
+                file_name: path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                size: ports.file_size(path)?,
+            })
+        })
+        .collect::<Result<Vec<_>, ExtractError>>()?;
+
+    // 手で置かれた既存ファイルは JSON として壊れていることがあるため、その場合は中身を null にして一覧には残す
+    let contents = ports.read_file(&model_info_path)?;
+    let model_info = serde_json::from_slice(&contents).ok();
+    let model_info_sha256 = hex(&Sha256::digest(&contents));
+
+    Ok(CatalogRecord {
+        directory: dir_path.to_path_buf(),
+        model_info_path,
+        model_info_sha256,
+        source,
+        source_archive,
+        models,
+        model_info,
+    })
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn generate_from_headers(
+    ports: &dyn FilePorts,
+    progress: &dyn ProgressReporter,
+    stats: &AtomicExtractStats,
+    output_dir: &Path,
+    mut safetensors_files: Vec<PathBuf>,
+) -> Result<Option<PathBuf>, ExtractError> {
+    // 複数のモデルがある場合でも結果が実行ごとに変わらないよう名前順で最初に見つかったものを使う
+    safetensors_files.sort();
+
+    for model_path in safetensors_files {
+        match ports.generate_model_info_from_header(&model_path, output_dir)? {
+            HeaderOutcome::Generated(path) => {
+                stats.increment_generated_from_header();
+                progress.on_update(&stats.snapshot());
+                return Ok(Some(path));
+            }
+            HeaderOutcome::InvalidHeader(reason) => {
+                stats.increment_invalid_headers();
+                progress.on_invalid_header(&model_path, &reason);
+            }
+            HeaderOutcome::NoMetadata => {}
+        }
+    }
 
-    Ok(final_stats)
+    Ok(None)
 }
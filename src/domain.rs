@@ -1,9 +1,635 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+use globset::{Glob, GlobBuilder, GlobMatcher, GlobSet, GlobSetBuilder};
+use serde::Serialize;
+use serde_json::Value;
+
 pub const MODEL_INFO_FILE_NAME: &str = "model_info.json";
+pub const SAFETENSORS_EXTENSION: &str = "safetensors";
+pub const DEFAULT_MODEL_EXTENSIONS: &[&str] = &[SAFETENSORS_EXTENSION];
+pub const TRAINING_SIDECAR_SUFFIX: &str = "training.json";
+pub const GGUF_EXTENSION: &str = "gguf";
+pub const GGUF_SIDECAR_SUFFIX: &str = "gguf.json";
+pub const ZIP_PASSWORD_FILE_NAME: &str = ".zip-password";
+pub const DEFAULT_MAX_ENTRY_SIZE: u64 = 512 * 1024 * 1024;
+pub const DEFAULT_MAX_COMPRESSION_RATIO: u64 = 100;
+// 小さなエントリは圧縮率が高くても害がないので、この大きさまでは圧縮率で止めない
+const COMPRESSION_RATIO_GRACE: u64 = 1024 * 1024;
+const TRAINING_KEY_PREFIX: &str = "ss_";
+// バージョン管理、キャッシュ、ゴミ箱、NAS のスナップショットやサムネイル用のディレクトリ
+pub const DEFAULT_EXCLUDES: &[&str] = &[
+    "**/.git",
+    "**/.hg",
+    "**/.svn",
+    "**/.cache",
+    "**/.Trash",
+    "**/.Trash-*",
+    "**/$RECYCLE.BIN",
+    "**/System Volume Information",
+    "**/.snapshot",
+    "**/.snapshots",
+    "**/@eaDir",
+];
 
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct ExtractStats {
     pub directories_scanned: u64,
-    pub safetensors_directories: u64,
+    pub model_directories: u64,
+    pub model_directories_by_extension: BTreeMap<String, u64>,
     pub zip_files_checked: u64,
+    pub invalid_zips: u64,
+    pub password_required: u64,
     pub extracted: u64,
+    pub extracted_by_entry: BTreeMap<String, u64>,
+    pub invalid_json: u64,
+    pub entries_too_large: u64,
+    pub invalid_headers: u64,
+    pub unreadable_directories: u64,
+    pub write_failures: u64,
+    pub skipped_existing: u64,
+    pub skipped_unchanged: u64,
+    pub skipped_not_newer: u64,
+    pub skipped_cached: u64,
+    pub backed_up: u64,
+    pub schema_violations: u64,
+    pub generated_from_header: u64,
+    pub training_reports: u64,
+    pub gguf_metadata: u64,
+}
+
+impl ExtractStats {
+    // 実行自体は最後まで進んだが、一部のファイルを処理できなかった件数
+    pub fn failures(&self) -> u64 {
+        self.invalid_zips
+            + self.password_required
+            + self.invalid_json
+            + self.entries_too_large
+            + self.invalid_headers
+            + self.unreadable_directories
+            + self.write_failures
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ReportedError {
+    pub kind: String,
+    pub path: PathBuf,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct RunSummary {
+    pub version: String,
+    pub root: PathBuf,
+    pub elapsed_seconds: f64,
+    pub stats: ExtractStats,
+    pub errors: Vec<ReportedError>,
+}
+
+#[derive(Debug, Clone)]
+pub struct EntryPatterns {
+    patterns: Vec<(String, GlobMatcher)>,
+}
+
+impl EntryPatterns {
+    pub fn new<I, S>(patterns: I) -> Result<Self, globset::Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let patterns = patterns
+            .into_iter()
+            .map(|pattern| {
+                let pattern = pattern.as_ref();
+                let matcher = Glob::new(pattern)?.compile_matcher();
+                Ok((pattern.to_string(), matcher))
+            })
+            .collect::<Result<Vec<_>, globset::Error>>()?;
+
+        Ok(Self { patterns })
+    }
+
+    pub fn patterns(&self) -> impl Iterator<Item = &str> {
+        self.patterns.iter().map(|(pattern, _)| pattern.as_str())
+    }
+
+    // 複数のパターンに一致する場合は統計を二重に数えないよう最初のパターンを返す
+    pub fn find_match(&self, file_name: &str) -> Option<&str> {
+        self.patterns
+            .iter()
+            .find(|(_, matcher)| matcher.is_match(file_name))
+            .map(|(pattern, _)| pattern.as_str())
+    }
+}
+
+impl Default for EntryPatterns {
+    fn default() -> Self {
+        Self::new([MODEL_INFO_FILE_NAME]).expect("default entry pattern must be valid")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DirectoryFilter {
+    includes: Vec<(PathBuf, GlobMatcher)>,
+    excludes: GlobSet,
+}
+
+impl DirectoryFilter {
+    pub fn new<I, E, S, T>(includes: I, excludes: E) -> Result<Self, globset::Error>
+    where
+        I: IntoIterator<Item = S>,
+        E: IntoIterator<Item = T>,
+        S: AsRef<str>,
+        T: AsRef<str>,
+    {
+        let includes = includes
+            .into_iter()
+            .map(|pattern| {
+                let pattern = pattern.as_ref();
+                Ok((literal_prefix(pattern), path_glob(pattern)?.compile_matcher()))
+            })
+            .collect::<Result<Vec<_>, globset::Error>>()?;
+
+        let mut builder = GlobSetBuilder::new();
+        for pattern in excludes {
+            builder.add(path_glob(pattern.as_ref())?);
+        }
+
+        Ok(Self {
+            includes,
+            excludes: builder.build()?,
+        })
+    }
+
+    // 除外に当たるか、どの include にも届かないディレクトリは、その下をまるごと歩かずに済ませる
+    pub fn should_descend(&self, relative: &Path) -> bool {
+        if self.excludes.is_match(relative) {
+            return false;
+        }
+        self.includes.is_empty()
+            || self.includes.iter().any(|(prefix, _)| {
+                relative.starts_with(prefix) || prefix.starts_with(relative)
+            })
+    }
+
+    // include に一致したディレクトリは、その下のディレクトリもすべて対象にする
+    pub fn is_selected(&self, relative: &Path) -> bool {
+        self.includes.is_empty()
+            || relative.ancestors().any(|ancestor| {
+                self.includes
+                    .iter()
+                    .any(|(_, matcher)| matcher.is_match(ancestor))
+            })
+    }
+
+    pub fn matches(&self, relative: &Path) -> bool {
+        relative
+            .ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .all(|ancestor| self.should_descend(ancestor))
+            && self.is_selected(relative)
+    }
+}
+
+impl Default for DirectoryFilter {
+    fn default() -> Self {
+        Self::new(std::iter::empty::<&str>(), DEFAULT_EXCLUDES)
+            .expect("default exclude patterns must be valid")
+    }
+}
+
+// ルートからの相対パスに対して使うので、* が / をまたがないようにする
+fn path_glob(pattern: &str) -> Result<Glob, globset::Error> {
+    GlobBuilder::new(pattern.trim_end_matches('/'))
+        .literal_separator(true)
+        .build()
+}
+
+fn literal_prefix(pattern: &str) -> PathBuf {
+    Path::new(pattern)
+        .components()
+        .take_while(|component| match component {
+            Component::Normal(name) => !name
+                .to_string_lossy()
+                .contains(['*', '?', '[', ']', '{', '}', '\\']),
+            _ => false,
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelExtensions {
+    extensions: Vec<String>,
+}
+
+impl ModelExtensions {
+    pub fn new<I, S>(extensions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut normalized = Vec::new();
+        for extension in extensions {
+            let extension = extension.as_ref().trim_start_matches('.').to_ascii_lowercase();
+            if !extension.is_empty() && !normalized.contains(&extension) {
+                normalized.push(extension);
+            }
+        }
+
+        Self {
+            extensions: normalized,
+        }
+    }
+
+    pub fn extensions(&self) -> impl Iterator<Item = &str> {
+        self.extensions.iter().map(String::as_str)
+    }
+
+    pub fn find_match(&self, extension: &str) -> Option<&str> {
+        self.extensions
+            .iter()
+            .find(|candidate| candidate.eq_ignore_ascii_case(extension))
+            .map(String::as_str)
+    }
+}
+
+impl Default for ModelExtensions {
+    fn default() -> Self {
+        Self::new(DEFAULT_MODEL_EXTENSIONS)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    #[default]
+    Always,
+    Never,
+    IfDifferent,
+    IfNewer,
+    Backup,
+}
+
+impl OverwritePolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Always => "always",
+            Self::Never => "never",
+            Self::IfDifferent => "if-different",
+            Self::IfNewer => "if-newer",
+            Self::Backup => "backup",
+        }
+    }
+}
+
+// 読めないディレクトリや書き込めない出力先に出会ったときに、報告して残りを続けるか、その場で全体を止めるか
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    #[default]
+    Continue,
+    FailFast,
+}
+
+// 壊れたアーカイブや zip bomb でディスクを埋めないよう、1 エントリを展開してよい大きさを制限する
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntryLimits {
+    pub max_size: Option<u64>,
+    pub max_ratio: Option<u64>,
+}
+
+impl EntryLimits {
+    pub fn unlimited() -> Self {
+        Self {
+            max_size: None,
+            max_ratio: None,
+        }
+    }
+
+    // 圧縮後の大きさが分からない形式では、圧縮率の制限はかけられない
+    pub fn for_entry(&self, compressed_size: Option<u64>) -> Option<EntryLimit> {
+        let size = self.max_size.map(EntryLimit::Size);
+        let ratio = self.max_ratio.zip(compressed_size).map(|(ratio, compressed_size)| {
+            EntryLimit::Ratio {
+                ratio,
+                compressed_size,
+                bytes: ratio
+                    .saturating_mul(compressed_size)
+                    .max(COMPRESSION_RATIO_GRACE),
+            }
+        });
+
+        [size, ratio].into_iter().flatten().min_by_key(EntryLimit::bytes)
+    }
+}
+
+impl Default for EntryLimits {
+    fn default() -> Self {
+        Self {
+            max_size: Some(DEFAULT_MAX_ENTRY_SIZE),
+            max_ratio: Some(DEFAULT_MAX_COMPRESSION_RATIO),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryLimit {
+    Size(u64),
+    Ratio {
+        ratio: u64,
+        compressed_size: u64,
+        bytes: u64,
+    },
+}
+
+impl EntryLimit {
+    pub fn bytes(&self) -> u64 {
+        match self {
+            Self::Size(bytes) | Self::Ratio { bytes, .. } => *bytes,
+        }
+    }
+}
+
+impl fmt::Display for EntryLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Size(bytes) => write!(f, "larger than {bytes} bytes"),
+            Self::Ratio {
+                ratio,
+                compressed_size,
+                ..
+            } => write!(
+                f,
+                "expands more than {ratio}x from {compressed_size} compressed bytes"
+            ),
+        }
+    }
+}
+
+// 暗号化されたアーカイブに順に試すパスワード。ログに出さないよう Debug では件数だけを表示する
+#[derive(Default, Clone, PartialEq, Eq)]
+pub struct ZipPasswords {
+    passwords: Vec<String>,
+}
+
+impl ZipPasswords {
+    pub fn new<I, S>(passwords: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            passwords: passwords.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    // パスワードファイルは 1 行に 1 つ。Windows で作られたファイルの改行や空行は無視する
+    pub fn from_lines(contents: &str) -> Self {
+        Self::new(
+            contents
+                .lines()
+                .map(|line| line.trim_end_matches('\r'))
+                .filter(|line| !line.is_empty()),
+        )
+    }
+
+    pub fn extend(&mut self, other: ZipPasswords) {
+        for password in other.passwords {
+            if !self.passwords.contains(&password) {
+                self.passwords.push(password);
+            }
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.passwords.iter().map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passwords.is_empty()
+    }
+}
+
+impl fmt::Debug for ZipPasswords {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ZipPasswords({} redacted)", self.passwords.len())
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ExtractOptions {
+    pub entries: EntryPatterns,
+    pub overwrite: OverwritePolicy,
+    pub on_error: ErrorPolicy,
+    pub passwords: ZipPasswords,
+    pub limits: EntryLimits,
+    pub output_root: Option<PathBuf>,
+    pub directories: DirectoryFilter,
+    pub model_extensions: ModelExtensions,
+    pub header_fallback: bool,
+    pub training_report: bool,
+    pub gguf_metadata: bool,
+    pub schema: Option<ModelInfoSchema>,
+    pub validate_existing: bool,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ModelInfoSchema {
+    validator: jsonschema::Validator,
+}
+
+impl ModelInfoSchema {
+    pub fn new(schema: &Value) -> Result<Self, jsonschema::ValidationError<'static>> {
+        Ok(Self {
+            validator: jsonschema::validator_for(schema)?,
+        })
+    }
+
+    pub fn validate(&self, instance: &Value) -> Vec<SchemaViolation> {
+        self.validator
+            .iter_errors(instance)
+            .map(|error| SchemaViolation {
+                pointer: error.instance_path().to_string(),
+                message: error.to_string(),
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct SchemaViolation {
+    pub pointer: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TrainingMetadata {
+    pub output_name: Option<String>,
+    pub base_model_name: Option<String>,
+    pub base_model_hash: Option<String>,
+    pub network_module: Option<String>,
+    pub network_dim: Option<u32>,
+    pub network_alpha: Option<f64>,
+    pub epoch: Option<u32>,
+    pub num_epochs: Option<u32>,
+    pub steps: Option<u64>,
+    pub learning_rate: Option<f64>,
+    pub tag_frequency: BTreeMap<String, u64>,
+    pub raw: BTreeMap<String, String>,
+}
+
+impl TrainingMetadata {
+    // kohya-ss/sd-scripts が書き込む ss_* キーが一つもなければ学習メタデータなしとみなす
+    pub fn from_metadata(metadata: &BTreeMap<String, String>) -> Option<Self> {
+        let raw = metadata
+            .iter()
+            .filter(|(key, _)| key.starts_with(TRAINING_KEY_PREFIX))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect::<BTreeMap<_, _>>();
+        if raw.is_empty() {
+            return None;
+        }
+
+        let text = |key: &str| {
+            raw.get(key)
+                .filter(|value| !value.is_empty() && value.as_str() != "None")
+                .cloned()
+        };
+        let number = |key: &str| text(key).and_then(|value| value.trim().parse::<f64>().ok());
+
+        Some(Self {
+            output_name: text("ss_output_name"),
+            base_model_name: text("ss_sd_model_name"),
+            base_model_hash: text("ss_new_sd_model_hash").or_else(|| text("ss_sd_model_hash")),
+            network_module: text("ss_network_module"),
+            network_dim: number("ss_network_dim").map(|value| value as u32),
+            network_alpha: number("ss_network_alpha"),
+            epoch: number("ss_epoch").map(|value| value as u32),
+            num_epochs: number("ss_num_epochs").map(|value| value as u32),
+            steps: number("ss_steps").map(|value| value as u64),
+            learning_rate: number("ss_learning_rate"),
+            tag_frequency: text("ss_tag_frequency")
+                .map(|value| parse_tag_frequency(&value))
+                .unwrap_or_default(),
+            raw,
+        })
+    }
+
+    pub fn top_tags(&self, limit: usize) -> Vec<(&str, u64)> {
+        let mut tags = self
+            .tag_frequency
+            .iter()
+            .map(|(tag, count)| (tag.as_str(), *count))
+            .collect::<Vec<_>>();
+        tags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        tags.truncate(limit);
+        tags
+    }
+}
+
+// ss_tag_frequency はデータセットごとの {タグ: 出現数} なので、モデル単位で合算する
+fn parse_tag_frequency(value: &str) -> BTreeMap<String, u64> {
+    let Ok(Value::Object(datasets)) = serde_json::from_str(value) else {
+        return BTreeMap::new();
+    };
+
+    let mut totals = BTreeMap::new();
+    for tags in datasets.values() {
+        let Some(tags) = tags.as_object() else {
+            continue;
+        };
+        for (tag, count) in tags {
+            if let Some(count) = count.as_u64() {
+                *totals.entry(tag.trim().to_string()).or_insert(0) += count;
+            }
+        }
+    }
+    totals
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TrainingRecord {
+    pub model_path: PathBuf,
+    pub sidecar_path: PathBuf,
+    pub metadata: TrainingMetadata,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum PlannedAction {
+    Create,
+    Replace,
+    Keep,
+}
+
+impl PlannedAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Create => "create",
+            Self::Replace => "replace",
+            Self::Keep => "keep",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct PlannedEntry {
+    pub pattern: String,
+    pub entry: String,
+    pub target: PathBuf,
+    pub action: PlannedAction,
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct PlannedArchive {
+    pub path: PathBuf,
+    pub entries: Vec<PlannedEntry>,
+    pub invalid: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ExtractPlan {
+    pub archives: Vec<PlannedArchive>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CatalogSource {
+    Extracted,
+    Generated,
+    Existing,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CatalogModel {
+    pub file_name: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CatalogRecord {
+    pub directory: PathBuf,
+    pub model_info_path: PathBuf,
+    pub model_info_sha256: String,
+    pub source: CatalogSource,
+    pub source_archive: Option<PathBuf>,
+    pub models: Vec<CatalogModel>,
+    pub model_info: Option<Value>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExtractReport {
+    pub stats: ExtractStats,
+    pub training: Vec<TrainingRecord>,
+    pub plan: Option<ExtractPlan>,
 }
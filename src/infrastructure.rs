@@ -1,22 +1,59 @@
+use std::collections::HashSet;
 use std::ffi::OsStr;
 use std::fs;
-use std::io::{self, Write};
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
 use std::sync::Mutex;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 use console::style;
 use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use serde::de::IgnoredAny;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 use walkdir::WalkDir;
 
-use crate::application::{ExtractError, FilePorts, ProgressReporter, ZipEntryOutcome};
-use crate::domain::ExtractStats;
+use crate::application::{
+    hex, ArchiveRecord, BoxError, CatalogSink, ExtractError, FilePorts, HeaderMetadata,
+    HeaderOutcome, InvalidArchive, InvalidArchiveKind, ProgressReporter, SkipReason,
+    ZipEntryOutcome,
+};
+use crate::domain::{
+    CatalogRecord, DirectoryFilter, EntryPatterns, ExtractOptions, ExtractPlan, ExtractStats, ModelInfoSchema,
+    OverwritePolicy, PlannedAction, PlannedEntry, ReportedError, SchemaViolation,
+    TrainingMetadata, TrainingRecord, ZipPasswords, GGUF_SIDECAR_SUFFIX, MODEL_INFO_FILE_NAME,
+    TRAINING_SIDECAR_SUFFIX, ZIP_PASSWORD_FILE_NAME,
+};
 
-pub struct FsPorts;
+mod archive;
+mod gguf;
+mod safetensors;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod state;
+mod timestamp;
+mod watch;
+
+pub use self::archive::{
+    Archive, ArchiveEntry, ArchiveError, ArchiveProbe, ArchiveReader, ArchiveRegistry, GzReader,
+    TarReader, ZipReader,
+};
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::SqliteCatalog;
+pub use self::state::{StateCache, DEFAULT_STATE_FILE_NAME};
+pub use self::watch::{ChangeBatch, ChangeWatcher, DEFAULT_DEBOUNCE};
+
+pub struct FsPorts {
+    archives: ArchiveRegistry,
+}
 
 impl FsPorts {
     pub fn new() -> Self {
-        Self
+        Self::with_archive_registry(ArchiveRegistry::new())
+    }
+
+    pub fn with_archive_registry(archives: ArchiveRegistry) -> Self {
+        Self { archives }
     }
 }
 
@@ -24,13 +61,37 @@ impl FilePorts for FsPorts {
     fn for_each_directory(
         &self,
         root: &Path,
+        filter: &DirectoryFilter,
         on_dir: &mut dyn FnMut(PathBuf) -> Result<(), ExtractError>,
+        on_error: &mut dyn FnMut(PathBuf, ExtractError) -> Result<(), ExtractError>,
     ) -> Result<(), ExtractError> {
-        for entry in WalkDir::new(root).follow_links(false) {
-            let entry = entry.map_err(|err| ExtractError::Message(err.to_string()))?;
+        let relative = |path: &Path| path.strip_prefix(root).unwrap_or(path).to_path_buf();
+        // filter_entry で枝ごと刈り込み、除外したディレクトリの中には入らない
+        let walker = WalkDir::new(root)
+            .follow_links(false)
+            .into_iter()
+            .filter_entry(|entry| {
+                entry.depth() == 0
+                    || !entry.file_type().is_dir()
+                    || filter.should_descend(&relative(entry.path()))
+            });
+        let mut last_dir = None;
+        for entry in walker {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    let path = err.path().unwrap_or(root).to_path_buf();
+                    // 直前に渡したディレクトリが読めない場合は、その一覧取得の失敗として一度だけ報告させる
+                    if last_dir.as_ref() != Some(&path) {
+                        on_error(path.clone(), directory_error(&path, err.into()))?;
+                    }
+                    continue;
+                }
+            };
 
-            if entry.file_type().is_dir() {
+            if entry.file_type().is_dir() && filter.is_selected(&relative(entry.path())) {
                 on_dir(entry.path().to_path_buf())?;
+                last_dir = Some(entry.path().to_path_buf());
             }
         }
 
@@ -40,9 +101,10 @@ impl FilePorts for FsPorts {
     fn list_files_in_dir(&self, dir: &Path) -> Result<Vec<PathBuf>, ExtractError> {
         let mut files = Vec::new();
 
-        for entry in fs::read_dir(dir)? {
-            let entry = entry?;
-            let file_type = entry.file_type()?;
+        let listing_error = |err| directory_error(dir, err);
+        for entry in fs::read_dir(dir).map_err(listing_error)? {
+            let entry = entry.map_err(listing_error)?;
+            let file_type = entry.file_type().map_err(listing_error)?;
 
             if file_type.is_file() {
                 files.push(entry.path());
@@ -52,58 +114,966 @@ impl FilePorts for FsPorts {
         Ok(files)
     }
 
+    fn is_archive(&self, path: &Path) -> bool {
+        self.archives.detect(path).is_some()
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
     fn extract_zip_entry_if_exists(
         &self,
         zip_path: &Path,
-        entry_name: &str,
+        options: &ExtractOptions,
         output_dir: &Path,
-    ) -> Result<ZipEntryOutcome, ExtractError> {
-        let file = match fs::File::open(zip_path) {
-            Ok(file) => file,
-            Err(err) => {
-                // 破損や読み取り不能でも全体処理を止めないため
-                return Ok(ZipEntryOutcome::InvalidZip(err.to_string()));
+    ) -> Result<Vec<ZipEntryOutcome>, ExtractError> {
+        let Some(reader) = self.archives.detect(zip_path) else {
+            return Ok(vec![ZipEntryOutcome::InvalidZip(InvalidArchive::new(
+                zip_path,
+                InvalidArchiveKind::Unsupported,
+                "no archive reader for this file",
+            ))]);
+        };
+
+        let mut selector = EntrySelector::new(&options.entries, output_dir);
+        let mut passwords = PasswordCandidates::new(zip_path, &options.passwords);
+
+        // 破損や読み取り不能でも全体処理を止めないため。書き込み側の失敗はアーカイブの問題ではないので呼び出し元に返す
+        match extract_from_archive(reader, zip_path, options, &mut passwords, &mut selector) {
+            Ok(()) => Ok(selector.finish()),
+            Err(ExtractFailure::Archive(err)) if err.kind == InvalidArchiveKind::Encrypted => {
+                Ok(selector.fail(ZipEntryOutcome::PasswordRequired(err)))
             }
+            Err(ExtractFailure::Archive(err)) => {
+                Ok(selector.fail(ZipEntryOutcome::InvalidZip(err)))
+            }
+            Err(ExtractFailure::Output(path, err)) => Err(output_error(&path, err)),
+        }
+    }
+
+    fn generate_model_info_from_header(
+        &self,
+        model_path: &Path,
+        output_dir: &Path,
+    ) -> Result<HeaderOutcome, ExtractError> {
+        let metadata = match safetensors::read_metadata(model_path) {
+            Ok(Some(metadata)) if !metadata.is_empty() => metadata,
+            Ok(_) => return Ok(HeaderOutcome::NoMetadata),
+            Err(err) => return Ok(HeaderOutcome::InvalidHeader(err.to_string())),
         };
-        let mut archive = match zip::ZipArchive::new(file) {
-            Ok(archive) => archive,
-            Err(err) => {
-                return Ok(ZipEntryOutcome::InvalidZip(err.to_string()));
+
+        let output_path = output_dir.join(MODEL_INFO_FILE_NAME);
+        let contents = serde_json::to_vec_pretty(&safetensors::metadata_to_json(&metadata))
+            .map_err(|err| output_error(&output_path, err))?;
+        write_output(&output_path, &contents)?;
+
+        Ok(HeaderOutcome::Generated(output_path))
+    }
+
+    fn read_model_metadata(&self, model_path: &Path) -> Result<HeaderMetadata, ExtractError> {
+        match safetensors::read_metadata(model_path) {
+            Ok(Some(metadata)) => Ok(HeaderMetadata::Found(metadata)),
+            Ok(None) => Ok(HeaderMetadata::NoMetadata),
+            Err(err) => Ok(HeaderMetadata::InvalidHeader(err.to_string())),
+        }
+    }
+
+    fn write_training_sidecar(
+        &self,
+        model_path: &Path,
+        output_dir: &Path,
+        metadata: &TrainingMetadata,
+    ) -> Result<PathBuf, ExtractError> {
+        let sidecar_path = sidecar_path(model_path, output_dir, TRAINING_SIDECAR_SUFFIX);
+        let contents =
+            serde_json::to_vec_pretty(metadata).map_err(|err| output_error(&sidecar_path, err))?;
+        write_output(&sidecar_path, &contents)?;
+
+        Ok(sidecar_path)
+    }
+
+    fn write_gguf_metadata(
+        &self,
+        model_path: &Path,
+        output_dir: &Path,
+    ) -> Result<HeaderOutcome, ExtractError> {
+        let metadata = match gguf::read_metadata(model_path) {
+            Ok(metadata) => metadata,
+            Err(err) => return Ok(HeaderOutcome::InvalidHeader(err.to_string())),
+        };
+
+        let sidecar_path = sidecar_path(model_path, output_dir, GGUF_SIDECAR_SUFFIX);
+        let contents =
+            serde_json::to_vec_pretty(&metadata).map_err(|err| output_error(&sidecar_path, err))?;
+        write_output(&sidecar_path, &contents)?;
+
+        Ok(HeaderOutcome::Generated(sidecar_path))
+    }
+
+    fn read_file(&self, path: &Path) -> Result<Vec<u8>, ExtractError> {
+        fs::read(path).map_err(|err| read_error(path, err))
+    }
+
+    fn file_size(&self, path: &Path) -> Result<u64, ExtractError> {
+        fs::metadata(path)
+            .map(|metadata| metadata.len())
+            .map_err(|err| read_error(path, err))
+    }
+}
+
+// サイドカーはモデルと同じ名前で出力先ディレクトリに置く
+fn sidecar_path(model_path: &Path, output_dir: &Path, suffix: &str) -> PathBuf {
+    let file_name = model_path.file_name().unwrap_or_default();
+    output_dir.join(file_name).with_extension(suffix)
+}
+
+fn write_output(path: &Path, contents: &[u8]) -> Result<(), ExtractError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| output_error(parent, err))?;
+    }
+    fs::write(path, contents).map_err(|err| output_error(path, err))
+}
+
+// serde_json や csv のエラーも io::Error に変換できるので、書き出しの失敗はすべてここで出力側のエラーにする
+fn output_error(path: &Path, err: impl Into<io::Error>) -> ExtractError {
+    ExtractError::Write {
+        path: path.to_path_buf(),
+        source: err.into(),
+    }
+}
+
+fn read_error(path: &Path, err: io::Error) -> ExtractError {
+    ExtractError::Read {
+        path: path.to_path_buf(),
+        source: err,
+    }
+}
+
+fn directory_error(path: &Path, err: io::Error) -> ExtractError {
+    ExtractError::ReadDirectory {
+        path: path.to_path_buf(),
+        source: err,
+    }
+}
+
+pub struct CatalogWriter {
+    state: Mutex<CatalogWriterState>,
+}
+
+struct CatalogWriterState {
+    path: PathBuf,
+    writer: io::BufWriter<fs::File>,
+    json_lines: bool,
+    records: u64,
+}
+
+impl CatalogWriter {
+    // .jsonl なら 1 行 1 レコード、それ以外は JSON 配列として、どちらも見つかった順に書き出す
+    pub fn create(path: &Path) -> Result<Self, ExtractError> {
+        let json_lines = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("jsonl"));
+        let file = fs::File::create(path).map_err(|err| output_error(path, err))?;
+        let mut writer = io::BufWriter::new(file);
+        if !json_lines {
+            writer.write_all(b"[").map_err(|err| output_error(path, err))?;
+        }
+
+        Ok(Self {
+            state: Mutex::new(CatalogWriterState {
+                path: path.to_path_buf(),
+                writer,
+                json_lines,
+                records: 0,
+            }),
+        })
+    }
+
+    pub fn finish(self) -> Result<u64, ExtractError> {
+        let mut state = self.state.into_inner().unwrap_or_else(|err| err.into_inner());
+        state.finish().map_err(|err| output_error(&state.path, err))?;
+        Ok(state.records)
+    }
+}
+
+impl CatalogWriterState {
+    fn push(&mut self, line: &[u8]) -> io::Result<()> {
+        let separator: &[u8] = match (self.json_lines, self.records) {
+            (true, _) => b"",
+            (false, 0) => b"\n",
+            (false, _) => b",\n",
+        };
+        self.writer.write_all(separator)?;
+        self.writer.write_all(line)?;
+        if self.json_lines {
+            self.writer.write_all(b"\n")?;
+        }
+        self.records += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if !self.json_lines {
+            if self.records > 0 {
+                self.writer.write_all(b"\n")?;
             }
+            self.writer.write_all(b"]\n")?;
+        }
+        self.writer.flush()
+    }
+}
+
+impl CatalogSink for CatalogWriter {
+    fn on_record(&self, record: &CatalogRecord) -> Result<(), ExtractError> {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(err) => err.into_inner(),
         };
 
-        for index in 0..archive.len() {
-            let mut entry = match archive.by_index(index) {
-                Ok(entry) => entry,
-                Err(err) => {
-                    return Ok(ZipEntryOutcome::InvalidZip(err.to_string()));
+        let line = serde_json::to_vec(record).map_err(|err| output_error(&state.path, err))?;
+        state
+            .push(&line)
+            .map_err(|err| output_error(&state.path, err))
+    }
+}
+
+pub struct RunReportWriter {
+    path: PathBuf,
+    records: Mutex<Vec<RunReportRecord>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct RunReportRecord {
+    path: PathBuf,
+    directory: PathBuf,
+    outcome: &'static str,
+    reason: Option<String>,
+    bytes_written: u64,
+    duration_ms: u64,
+}
+
+impl RunReportWriter {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            records: Mutex::new(Vec::new()),
+        }
+    }
+
+    // .csv なら CSV、それ以外は JSON 配列として、アーカイブのパス順に書き出す
+    pub fn finish(self) -> Result<usize, ExtractError> {
+        let mut records = self
+            .records
+            .into_inner()
+            .unwrap_or_else(|err| err.into_inner());
+        records.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let is_csv = self
+            .path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+        let output_failure = |err| output_error(&self.path, err);
+        if is_csv {
+            let mut writer = csv::Writer::from_path(&self.path).map_err(output_failure)?;
+            for record in &records {
+                writer.serialize(record).map_err(output_failure)?;
+            }
+            writer
+                .flush()
+                .map_err(|err| output_error(&self.path, err))?;
+        } else {
+            let contents = serde_json::to_vec_pretty(&records)
+                .map_err(|err| output_error(&self.path, err))?;
+            write_output(&self.path, &contents)?;
+        }
+
+        Ok(records.len())
+    }
+}
+
+impl CatalogSink for RunReportWriter {
+    fn on_archive(&self, record: &ArchiveRecord) -> Result<(), ExtractError> {
+        let report = RunReportRecord {
+            path: record.path.clone(),
+            directory: record.directory.clone(),
+            outcome: record.status(),
+            reason: record.reason(),
+            bytes_written: record.bytes_written(),
+            duration_ms: record.duration.as_millis() as u64,
+        };
+        match self.records.lock() {
+            Ok(mut records) => records.push(report),
+            Err(err) => err.into_inner().push(report),
+        }
+        Ok(())
+    }
+}
+
+pub fn load_schema(path: &Path) -> Result<ModelInfoSchema, ExtractError> {
+    let schema_error = |source: BoxError| ExtractError::Schema {
+        path: path.to_path_buf(),
+        source,
+    };
+    let contents = fs::read(path).map_err(|err| read_error(path, err))?;
+    let schema = serde_json::from_slice(&contents).map_err(|err| schema_error(err.into()))?;
+
+    ModelInfoSchema::new(&schema).map_err(|err| schema_error(err.into()))
+}
+
+pub fn write_training_summary(path: &Path, records: &[TrainingRecord]) -> Result<(), ExtractError> {
+    let is_csv = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+
+    let output_failure = |err| output_error(path, err);
+    if is_csv {
+        let mut writer = csv::Writer::from_path(path).map_err(output_failure)?;
+        writer
+            .write_record(TRAINING_SUMMARY_COLUMNS)
+            .map_err(output_failure)?;
+
+        for record in records {
+            let metadata = &record.metadata;
+            let top_tags = metadata
+                .top_tags(TRAINING_SUMMARY_TOP_TAGS)
+                .iter()
+                .map(|(tag, count)| format!("{tag}:{count}"))
+                .collect::<Vec<_>>()
+                .join(";");
+            let row = [
+                record.model_path.display().to_string(),
+                optional_cell(&metadata.output_name),
+                optional_cell(&metadata.base_model_name),
+                optional_cell(&metadata.base_model_hash),
+                optional_cell(&metadata.network_module),
+                optional_cell(&metadata.network_dim),
+                optional_cell(&metadata.network_alpha),
+                optional_cell(&metadata.epoch),
+                optional_cell(&metadata.num_epochs),
+                optional_cell(&metadata.steps),
+                optional_cell(&metadata.learning_rate),
+                top_tags,
+            ];
+            writer.write_record(&row).map_err(output_failure)?;
+        }
+
+        writer.flush().map_err(|err| output_error(path, err))?;
+    } else {
+        let contents =
+            serde_json::to_vec_pretty(records).map_err(|err| output_error(path, err))?;
+        fs::write(path, contents).map_err(|err| output_error(path, err))?;
+    }
+
+    Ok(())
+}
+
+pub fn format_plan_table(plan: &ExtractPlan) -> String {
+    let mut rows = vec![PLAN_TABLE_COLUMNS.map(str::to_string)];
+    for archive in &plan.archives {
+        let archive_path = archive.path.display().to_string();
+        if let Some(reason) = &archive.invalid {
+            rows.push([
+                "invalid".to_string(),
+                archive_path,
+                "-".to_string(),
+                "-".to_string(),
+                reason.clone(),
+            ]);
+            continue;
+        }
+        if archive.entries.is_empty() {
+            rows.push([
+                "none".to_string(),
+                archive_path,
+                "-".to_string(),
+                "-".to_string(),
+                "no matching entry".to_string(),
+            ]);
+            continue;
+        }
+        for entry in &archive.entries {
+            rows.push([
+                entry.action.as_str().to_string(),
+                archive_path.clone(),
+                entry.entry.clone(),
+                entry.target.display().to_string(),
+                entry.detail.clone().unwrap_or_default(),
+            ]);
+        }
+    }
+
+    let mut widths = [0; PLAN_TABLE_COLUMNS.len()];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut table = String::new();
+    for row in &rows {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        table.push_str(line.trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+const PLAN_TABLE_COLUMNS: [&str; 5] = ["ACTION", "ARCHIVE", "ENTRY", "TARGET", "DETAIL"];
+
+const TRAINING_SUMMARY_TOP_TAGS: usize = 10;
+const TRAINING_SUMMARY_COLUMNS: [&str; 12] = [
+    "model_path",
+    "output_name",
+    "base_model_name",
+    "base_model_hash",
+    "network_module",
+    "network_dim",
+    "network_alpha",
+    "epoch",
+    "num_epochs",
+    "steps",
+    "learning_rate",
+    "top_tags",
+];
+
+fn optional_cell<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map(ToString::to_string).unwrap_or_default()
+}
+
+fn extract_from_archive(
+    reader: &dyn ArchiveReader,
+    archive_path: &Path,
+    options: &ExtractOptions,
+    passwords: &mut PasswordCandidates,
+    selector: &mut EntrySelector,
+) -> Result<(), ExtractFailure> {
+    let policy = options.overwrite;
+    let archive_failure = |kind, err| ExtractFailure::archive(archive_path, kind, err);
+    let mut archive = reader
+        .open(archive_path)
+        .map_err(|err| archive_failure(InvalidArchiveKind::Unreadable, err))?;
+    // エントリに更新日時がない形式ではアーカイブ自体の更新日時で代用する
+    let archive_modified = fs::metadata(archive_path)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+
+    let entries = archive
+        .entries()
+        .map_err(|err| archive_failure(InvalidArchiveKind::Corrupt, err))?;
+    for (index, entry) in entries.into_iter().enumerate() {
+        if entry.is_dir {
+            continue;
+        }
+
+        let Some((pattern, output_path)) = selector.select(&entry.name) else {
+            continue;
+        };
+
+        let exists = output_path.exists();
+        let source_modified = entry.modified.or(archive_modified);
+        let limit = options.limits.for_entry(entry.compressed_size);
+        // 宣言された大きさで分かるものは、エントリを開かずに止める
+        let declared_too_large =
+            limit.filter(|limit| entry.size.is_some_and(|size| size > limit.bytes()));
+        let mut staged = None;
+        let decision = match (
+            skip_without_contents(policy, &output_path, source_modified)
+                .map_err(|err| ExtractFailure::Output(output_path.clone(), err))?,
+            declared_too_large,
+        ) {
+            (Some(reason), _) => EntryDecision::Keep(reason),
+            (None, Some(limit)) => EntryDecision::TooLarge(limit.to_string()),
+            (None, None) => {
+                // 途中で失敗しても既存ファイルが壊れないよう、同じディレクトリの一時ファイルに書き切ってから置き換える
+                let entry_failure = |err| {
+                    let failure = archive_failure(InvalidArchiveKind::Entry, err);
+                    failure.with_entry(&entry.name)
+                };
+                let max_bytes = limit.map(|limit| limit.bytes());
+                let entry_staged = stage_with_passwords(
+                    archive.as_mut(),
+                    index,
+                    passwords,
+                    &output_path,
+                    options.dry_run,
+                    max_bytes,
+                )
+                .map_err(|err| match err {
+                    StageFailure::Open(err) => entry_failure(err),
+                    StageFailure::Read(err) => entry_failure(ArchiveError::Io(err)),
+                    StageFailure::Output(err) => ExtractFailure::Output(output_path.clone(), err),
+                })?;
+                let decision = if entry_staged.too_large
+                    && let Some(limit) = limit
+                {
+                    EntryDecision::TooLarge(limit.to_string())
+                } else if let Some(reason) = entry_staged.json_error.clone() {
+                    // 壊れた JSON で既存の正しいファイルを上書きしないよう、置き換える前に検証する
+                    EntryDecision::InvalidJson(reason)
+                } else if policy == OverwritePolicy::IfDifferent
+                    && exists
+                    && file_digest(&output_path)
+                        .map_err(|err| ExtractFailure::Output(output_path.clone(), err))?
+                        == entry_staged.digest
+                {
+                    EntryDecision::Keep(SkipReason::Unchanged)
+                } else {
+                    EntryDecision::Write {
+                        backup: policy == OverwritePolicy::Backup && exists,
+                    }
+                };
+                staged = Some(entry_staged);
+                decision
+            }
+        };
+
+        if options.dry_run {
+            let (action, detail) = match decision {
+                EntryDecision::Keep(reason) => {
+                    (PlannedAction::Keep, Some(reason.as_str().to_string()))
+                }
+                EntryDecision::InvalidJson(reason) => {
+                    (PlannedAction::Keep, Some(format!("invalid json: {reason}")))
+                }
+                EntryDecision::TooLarge(reason) => {
+                    (PlannedAction::Keep, Some(format!("too large: {reason}")))
+                }
+                EntryDecision::Write { .. } if !exists => (PlannedAction::Create, None),
+                EntryDecision::Write { backup } => {
+                    (PlannedAction::Replace, backup.then(|| "backup".to_string()))
                 }
             };
+            selector.push(ZipEntryOutcome::Planned(PlannedEntry {
+                pattern,
+                entry: entry.name,
+                target: output_path,
+                action,
+                detail,
+            }));
+            continue;
+        }
 
-            if entry.is_dir() {
-                continue;
+        let outcome = match decision {
+            EntryDecision::Keep(reason) => ZipEntryOutcome::Skipped {
+                pattern,
+                path: output_path,
+                reason,
+            },
+            EntryDecision::InvalidJson(reason) => ZipEntryOutcome::InvalidJson {
+                pattern,
+                path: output_path,
+                reason,
+            },
+            EntryDecision::TooLarge(reason) => ZipEntryOutcome::TooLarge {
+                pattern,
+                path: output_path,
+                reason,
+            },
+            EntryDecision::Write { backup } => {
+                // 元のファイルは動かさずに別名を付けておき、置き換え自体は persist の rename に任せる。
+                // 途中で失敗しても元のパスには元の内容が残る
+                let backup = if backup {
+                    let backup_path = backup_path(&output_path);
+                    fs::hard_link(&output_path, &backup_path)
+                        .or_else(|_| fs::copy(&output_path, &backup_path).map(|_| ()))
+                        .map_err(|err| ExtractFailure::Output(backup_path.clone(), err))?;
+                    Some(backup_path)
+                } else {
+                    None
+                };
+                let (bytes, sha256) = match staged {
+                    Some(staged) => {
+                        if let Some(file) = staged.file
+                            && let Err(err) = file.persist(&output_path)
+                        {
+                            if let Some(backup_path) = &backup {
+                                let _ = fs::remove_file(backup_path);
+                            }
+                            return Err(ExtractFailure::Output(output_path.clone(), err.error));
+                        }
+                        (staged.bytes, hex(&staged.digest))
+                    }
+                    None => (0, String::new()),
+                };
+                ZipEntryOutcome::Extracted {
+                    pattern,
+                    path: output_path,
+                    backup,
+                    bytes,
+                    sha256,
+                }
             }
+        };
+        selector.push(outcome);
+    }
 
-            let entry_path = Path::new(entry.name());
-            let entry_file_name = entry_path.file_name();
+    Ok(())
+}
 
-            if entry_file_name == Some(OsStr::new(entry_name)) {
-                let output_path = output_dir.join(entry_name);
-                let mut output_file = match fs::File::create(output_path) {
-                    Ok(output_file) => output_file,
+// アーカイブと同じディレクトリの .zip-password を、指定されたパスワードより先に試す。
+// 暗号化されていないアーカイブには関係ないので、暗号化されたエントリに出会ってから読む
+struct PasswordCandidates<'a> {
+    zip_path: &'a Path,
+    configured: &'a ZipPasswords,
+    loaded: Option<ZipPasswords>,
+}
+
+impl<'a> PasswordCandidates<'a> {
+    fn new(zip_path: &'a Path, configured: &'a ZipPasswords) -> Self {
+        Self {
+            zip_path,
+            configured,
+            loaded: None,
+        }
+    }
+
+    // 読めないパスワードファイルは実行全体ではなく、そのアーカイブを開けない理由として報告する
+    fn load(&mut self) -> Result<&ZipPasswords, ArchiveError> {
+        let loaded = match self.loaded.take() {
+            Some(loaded) => loaded,
+            None => {
+                let password_file = self
+                    .zip_path
+                    .parent()
+                    .unwrap_or(Path::new("."))
+                    .join(ZIP_PASSWORD_FILE_NAME);
+                let mut candidates = match fs::read_to_string(&password_file) {
+                    Ok(contents) => ZipPasswords::from_lines(&contents),
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => ZipPasswords::default(),
                     Err(err) => {
-                        return Ok(ZipEntryOutcome::InvalidZip(err.to_string()));
+                        return Err(ArchiveError::Encrypted(format!(
+                            "cannot read {}: {err}",
+                            password_file.display()
+                        )));
                     }
                 };
-                if let Err(err) = io::copy(&mut entry, &mut output_file) {
-                    return Ok(ZipEntryOutcome::InvalidZip(err.to_string()));
-                }
+                candidates.extend(self.configured.clone());
+                candidates
+            }
+        };
+        Ok(self.loaded.insert(loaded))
+    }
+}
+
+enum ExtractFailure {
+    Archive(InvalidArchive),
+    Output(PathBuf, io::Error),
+}
+
+impl ExtractFailure {
+    // 暗号化されたエントリは、どの段階で見つかっても同じ種類として扱う
+    fn archive(path: &Path, kind: InvalidArchiveKind, err: ArchiveError) -> Self {
+        let kind = match (&err, kind) {
+            (ArchiveError::Encrypted(_), _) => InvalidArchiveKind::Encrypted,
+            (ArchiveError::Format(_), InvalidArchiveKind::Unreadable) => InvalidArchiveKind::Corrupt,
+            (_, kind) => kind,
+        };
+        Self::Archive(InvalidArchive::new(path, kind, err))
+    }
+
+    fn with_entry(self, entry: &str) -> Self {
+        match self {
+            Self::Archive(err) => Self::Archive(err.with_entry(entry)),
+            output => output,
+        }
+    }
+}
+
+enum StageFailure {
+    Open(ArchiveError),
+    Read(io::Error),
+    Output(io::Error),
+}
+
+enum EntryDecision {
+    Keep(SkipReason),
+    InvalidJson(String),
+    TooLarge(String),
+    Write { backup: bool },
+}
+
+// 展開しなくても判断できるポリシーは、エントリを読む前に既存ファイルを残すか決める
+fn skip_without_contents(
+    policy: OverwritePolicy,
+    output_path: &Path,
+    source_modified: Option<SystemTime>,
+) -> io::Result<Option<SkipReason>> {
+    let existing = match fs::metadata(output_path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
 
-                return Ok(ZipEntryOutcome::Extracted);
+    let reason = match policy {
+        OverwritePolicy::Never => Some(SkipReason::Exists),
+        OverwritePolicy::IfNewer => {
+            // 日時が分からない場合は判断できないため、従来どおり上書きする
+            match (source_modified, existing.modified().ok()) {
+                (Some(source), Some(target)) if source <= target => Some(SkipReason::NotNewer),
+                _ => None,
             }
         }
+        OverwritePolicy::Always | OverwritePolicy::IfDifferent | OverwritePolicy::Backup => None,
+    };
 
-        Ok(ZipEntryOutcome::NotFound)
+    Ok(reason)
+}
+
+struct StagedEntry {
+    file: Option<tempfile::NamedTempFile>,
+    bytes: u64,
+    digest: Vec<u8>,
+    json_error: Option<String>,
+    too_large: bool,
+}
+
+// 暗号化されていないエントリはパスワードなしで開ける。ZipCrypto のパスワード確認は 1 バイトしかなく
+// 違うパスワードでも 256 回に 1 回は通ってしまうので、CRC の検証まで読み切れたものを正しいパスワードとみなす
+fn stage_with_passwords(
+    archive: &mut dyn Archive,
+    index: usize,
+    passwords: &mut PasswordCandidates,
+    output_path: &Path,
+    dry_run: bool,
+    max_bytes: Option<u64>,
+) -> Result<StagedEntry, StageFailure> {
+    let passwords = match archive.open_entry(index) {
+        Ok(mut reader) => return stage_entry(&mut reader, output_path, dry_run, max_bytes),
+        Err(ArchiveError::Encrypted(reason)) => {
+            let passwords = passwords.load().map_err(StageFailure::Open)?;
+            if passwords.is_empty() {
+                return Err(StageFailure::Open(ArchiveError::Encrypted(reason)));
+            }
+            passwords
+        }
+        Err(err) => return Err(StageFailure::Open(err)),
+    };
+
+    let mut read_failure = None;
+    for password in passwords.iter() {
+        let staged = match archive.open_encrypted_entry(index, password) {
+            Ok(mut reader) => stage_entry(&mut reader, output_path, dry_run, max_bytes),
+            Err(ArchiveError::Encrypted(_)) => continue,
+            Err(err) => return Err(StageFailure::Open(err)),
+        };
+        match staged {
+            // 確認をすり抜けた違うパスワードかもしれないので、残りの候補も試す
+            Err(StageFailure::Read(err)) => read_failure = Some(err),
+            staged => return staged,
+        }
+    }
+
+    // どれでも読めなかったときは、確認を通ったのに読めなかった理由の方が手がかりになる
+    Err(match read_failure {
+        Some(err) => StageFailure::Read(err),
+        None => StageFailure::Open(ArchiveError::Encrypted(format!(
+            "no matching password ({} tried)",
+            passwords.iter().count()
+        ))),
+    })
+}
+
+// ドライランでは一時ファイルも作らず、ハッシュと JSON の検証だけを行う
+fn stage_entry(
+    entry_reader: &mut dyn Read,
+    output_path: &Path,
+    dry_run: bool,
+    max_bytes: Option<u64>,
+) -> Result<StagedEntry, StageFailure> {
+    let output_failure = StageFailure::Output;
+    let mut file = if dry_run {
+        None
+    } else {
+        let output_dir = output_path.parent().unwrap_or_else(|| Path::new("."));
+        let file_name = output_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        fs::create_dir_all(output_dir).map_err(output_failure)?;
+        let prefix = format!(".{file_name}.");
+        let mut builder = tempfile::Builder::new();
+        builder.prefix(&prefix).suffix(".tmp");
+        // tempfile は所有者だけが読める 0600 で作るので、普通のファイルと同じく umask に従わせる
+        #[cfg(unix)]
+        builder.permissions(std::os::unix::fs::PermissionsExt::from_mode(0o666));
+        let file = builder.tempfile_in(output_dir).map_err(output_failure)?;
+        // 置き換える既存ファイルがあれば、その権限を引き継ぐ
+        if let Ok(existing) = fs::metadata(output_path)
+            && existing.is_file()
+        {
+            file.as_file()
+                .set_permissions(existing.permissions())
+                .map_err(output_failure)?;
+        }
+        Some(file)
+    };
+
+    let is_json = output_path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+    let mut sink = io::sink();
+    let mut writer = StagingWriter {
+        inner: match file.as_mut() {
+            Some(file) => file.as_file_mut() as &mut dyn Write,
+            None => &mut sink,
+        },
+        hasher: Sha256::new(),
+        json: is_json.then(Vec::new),
+        write_error: None,
+    };
+    // 宣言された大きさは偽れるので、実際に読んだ量でも制限する。超えたかどうか分かるよう 1 バイト余分に読む
+    let mut limited = entry_reader.take(max_bytes.map_or(u64::MAX, |max| max.saturating_add(1)));
+    // zip のエントリは最後まで読み切ったときに CRC を検証し、不一致ならエラーを返す
+    let bytes = match io::copy(&mut limited, &mut writer) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            return Err(match writer.write_error.take() {
+                Some(write_error) => output_failure(write_error),
+                None => StageFailure::Read(err),
+            });
+        }
+    };
+
+    if max_bytes.is_some_and(|max| bytes > max) {
+        // 一時ファイルは破棄されるので、途中まで書いた内容は残らない
+        return Ok(StagedEntry {
+            file: None,
+            bytes,
+            digest: Vec::new(),
+            json_error: None,
+            too_large: true,
+        });
+    }
+
+    let digest = writer.hasher.finalize().to_vec();
+    let json_error = writer.json.and_then(|contents| {
+        serde_json::from_slice::<IgnoredAny>(&contents)
+            .err()
+            .map(|err| err.to_string())
+    });
+    if let Some(file) = &file {
+        file.as_file().sync_all().map_err(output_failure)?;
+    }
+
+    Ok(StagedEntry {
+        file,
+        bytes,
+        digest,
+        json_error,
+        too_large: false,
+    })
+}
+
+struct StagingWriter<'a> {
+    inner: &'a mut dyn Write,
+    hasher: Sha256,
+    json: Option<Vec<u8>>,
+    // io::copy は読み書きどちらの失敗も同じ io::Error で返すため、書き込み側の失敗をここで区別する
+    write_error: Option<io::Error>,
+}
+
+impl Write for StagingWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = match self.inner.write(buf) {
+            Ok(written) => written,
+            Err(err) => {
+                let kind = err.kind();
+                self.write_error = Some(err);
+                return Err(io::Error::from(kind));
+            }
+        };
+        self.hasher.update(&buf[..written]);
+        if let Some(json) = &mut self.json {
+            json.extend_from_slice(&buf[..written]);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn file_digest(path: &Path) -> io::Result<Vec<u8>> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().to_vec())
+}
+
+// 同じ秒に複数回実行しても既存のバックアップを潰さないよう連番を付ける
+fn backup_path(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let stamp = timestamp::format_compact(SystemTime::now());
+
+    let mut candidate = path.with_file_name(format!("{file_name}.{stamp}.bak"));
+    let mut counter = 1;
+    while candidate.exists() {
+        candidate = path.with_file_name(format!("{file_name}.{stamp}.{counter}.bak"));
+        counter += 1;
+    }
+    candidate
+}
+
+struct EntrySelector<'a> {
+    entries: &'a EntryPatterns,
+    output_dir: &'a Path,
+    written: HashSet<String>,
+    outcomes: Vec<ZipEntryOutcome>,
+}
+
+impl<'a> EntrySelector<'a> {
+    fn new(entries: &'a EntryPatterns, output_dir: &'a Path) -> Self {
+        Self {
+            entries,
+            output_dir,
+            written: HashSet::new(),
+            outcomes: Vec::new(),
+        }
+    }
+
+    fn select(&mut self, entry_name: &str) -> Option<(String, PathBuf)> {
+        let entry_file_name = Path::new(entry_name).file_name().and_then(OsStr::to_str)?;
+        let pattern = self.entries.find_match(entry_file_name)?;
+
+        // 同名エントリが複数あるときは最初の一件だけを採用する
+        if !self.written.insert(entry_file_name.to_string()) {
+            return None;
+        }
+
+        Some((pattern.to_string(), self.output_dir.join(entry_file_name)))
+    }
+
+    fn push(&mut self, outcome: ZipEntryOutcome) {
+        self.outcomes.push(outcome);
+    }
+
+    fn finish(self) -> Vec<ZipEntryOutcome> {
+        if self.outcomes.is_empty() {
+            vec![ZipEntryOutcome::NotFound]
+        } else {
+            self.outcomes
+        }
+    }
+
+    // 途中のエントリで失敗しても、それまでに書き込んだエントリは集計や記録に残す
+    fn fail(mut self, failure: ZipEntryOutcome) -> Vec<ZipEntryOutcome> {
+        self.outcomes.push(failure);
+        self.outcomes
+    }
+}
+
+impl Default for FsPorts {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -115,12 +1085,30 @@ impl NoProgressReporter {
     }
 }
 
+impl Default for NoProgressReporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ProgressReporter for NoProgressReporter {
     fn on_start(&self, _root: &Path) {}
 
     fn on_update(&self, _stats: &ExtractStats) {}
 
-    fn on_invalid_zip(&self, _zip_path: &Path, _reason: &str) {}
+    fn on_invalid_zip(&self, _zip_path: &Path, _error: &InvalidArchive) {}
+
+    fn on_password_required(&self, _zip_path: &Path, _error: &InvalidArchive) {}
+
+    fn on_invalid_header(&self, _model_path: &Path, _reason: &str) {}
+
+    fn on_invalid_json(&self, _zip_path: &Path, _target_path: &Path, _reason: &str) {}
+
+    fn on_entry_too_large(&self, _zip_path: &Path, _target_path: &Path, _reason: &str) {}
+
+    fn on_schema_violation(&self, _path: &Path, _violations: &[SchemaViolation]) {}
+
+    fn on_error(&self, _path: &Path, _error: &ExtractError) {}
 
     fn on_finish(&self, _stats: &ExtractStats) {}
 }
@@ -146,11 +1134,15 @@ impl IndicatifProgressReporter {
     }
 }
 
+impl Default for IndicatifProgressReporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ProgressReporter for IndicatifProgressReporter {
     fn on_start(&self, root: &Path) {
-        let _ = self
-            .bar
-            .println(format!("scanning: {}", root.display()));
+        self.bar.println(format!("scanning: {}", root.display()));
         self.bar.set_message(format_stats(&ExtractStats::default()));
     }
 
@@ -158,9 +1150,49 @@ impl ProgressReporter for IndicatifProgressReporter {
         self.bar.set_message(format_stats(stats));
     }
 
-    fn on_invalid_zip(&self, zip_path: &Path, reason: &str) {
-        let message = format!("invalid zip: {} ({})", zip_path.display(), reason);
-        let _ = self.bar.println(style(message).red().to_string());
+    fn on_invalid_zip(&self, zip_path: &Path, error: &InvalidArchive) {
+        let message = format!("invalid zip: {} ({})", zip_path.display(), error);
+        self.bar.println(style(message).red().to_string());
+    }
+
+    fn on_password_required(&self, zip_path: &Path, error: &InvalidArchive) {
+        let message = format!("password required: {} ({})", zip_path.display(), error);
+        self.bar.println(style(message).yellow().to_string());
+    }
+
+    fn on_invalid_header(&self, model_path: &Path, reason: &str) {
+        let message = format!("invalid header: {} ({})", model_path.display(), reason);
+        self.bar.println(style(message).red().to_string());
+    }
+
+    fn on_invalid_json(&self, zip_path: &Path, target_path: &Path, reason: &str) {
+        let message = format!(
+            "invalid json: {} from {} ({})",
+            target_path.display(),
+            zip_path.display(),
+            reason
+        );
+        self.bar.println(style(message).red().to_string());
+    }
+
+    fn on_entry_too_large(&self, zip_path: &Path, target_path: &Path, reason: &str) {
+        let message = format!(
+            "entry too large: {} from {} ({})",
+            target_path.display(),
+            zip_path.display(),
+            reason
+        );
+        self.bar.println(style(message).red().to_string());
+    }
+
+    fn on_schema_violation(&self, path: &Path, violations: &[SchemaViolation]) {
+        let message = format_schema_violation(path, violations);
+        self.bar.println(style(message).red().to_string());
+    }
+
+    fn on_error(&self, path: &Path, error: &ExtractError) {
+        let message = format!("error: {} ({})", path.display(), error);
+        self.bar.println(style(message).red().to_string());
     }
 
     fn on_finish(&self, stats: &ExtractStats) {
@@ -169,6 +1201,104 @@ impl ProgressReporter for IndicatifProgressReporter {
     }
 }
 
+// 進捗表示はそのまま内側に渡しつつ、失敗だけを実行後の集計用に貯めておく
+pub struct ErrorCollectingReporter<'a> {
+    inner: &'a dyn ProgressReporter,
+    errors: Mutex<Vec<ReportedError>>,
+}
+
+impl<'a> ErrorCollectingReporter<'a> {
+    pub fn new(inner: &'a dyn ProgressReporter) -> Self {
+        Self {
+            inner,
+            errors: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn into_errors(self) -> Vec<ReportedError> {
+        self.errors.into_inner().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn push(&self, kind: &str, path: &Path, message: String) {
+        let error = ReportedError {
+            kind: kind.to_string(),
+            path: path.to_path_buf(),
+            message,
+        };
+        match self.errors.lock() {
+            Ok(mut errors) => errors.push(error),
+            Err(err) => err.into_inner().push(error),
+        }
+    }
+}
+
+impl ProgressReporter for ErrorCollectingReporter<'_> {
+    fn on_start(&self, root: &Path) {
+        self.inner.on_start(root);
+    }
+
+    fn on_update(&self, stats: &ExtractStats) {
+        self.inner.on_update(stats);
+    }
+
+    fn on_invalid_zip(&self, zip_path: &Path, error: &InvalidArchive) {
+        self.push("invalid_zip", zip_path, error.to_string());
+        self.inner.on_invalid_zip(zip_path, error);
+    }
+
+    fn on_password_required(&self, zip_path: &Path, error: &InvalidArchive) {
+        self.push("password_required", zip_path, error.to_string());
+        self.inner.on_password_required(zip_path, error);
+    }
+
+    fn on_invalid_header(&self, model_path: &Path, reason: &str) {
+        self.push("invalid_header", model_path, reason.to_string());
+        self.inner.on_invalid_header(model_path, reason);
+    }
+
+    fn on_invalid_json(&self, zip_path: &Path, target_path: &Path, reason: &str) {
+        self.push(
+            "invalid_json",
+            target_path,
+            format!("from {}: {}", zip_path.display(), reason),
+        );
+        self.inner.on_invalid_json(zip_path, target_path, reason);
+    }
+
+    fn on_entry_too_large(&self, zip_path: &Path, target_path: &Path, reason: &str) {
+        self.push(
+            "entry_too_large",
+            target_path,
+            format!("from {}: {}", zip_path.display(), reason),
+        );
+        self.inner.on_entry_too_large(zip_path, target_path, reason);
+    }
+
+    fn on_schema_violation(&self, path: &Path, violations: &[SchemaViolation]) {
+        let message = violations
+            .iter()
+            .map(|violation| format!("{}: {}", violation.pointer, violation.message))
+            .collect::<Vec<_>>()
+            .join("; ");
+        self.push("schema_violation", path, message);
+        self.inner.on_schema_violation(path, violations);
+    }
+
+    fn on_error(&self, path: &Path, error: &ExtractError) {
+        let kind = if error.is_output() {
+            "write_failure"
+        } else {
+            "unreadable_directory"
+        };
+        self.push(kind, path, error.to_string());
+        self.inner.on_error(path, error);
+    }
+
+    fn on_finish(&self, stats: &ExtractStats) {
+        self.inner.on_finish(stats);
+    }
+}
+
 struct LineProgressState<W: Write> {
     writer: W,
     last_stats: ExtractStats,
@@ -185,6 +1315,12 @@ impl LineProgressReporter<std::io::Stderr> {
     }
 }
 
+impl Default for LineProgressReporter<std::io::Stderr> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<W: Write + Send> LineProgressReporter<W> {
     pub fn with_writer(writer: W) -> Self {
         Self {
@@ -233,17 +1369,17 @@ impl<W: Write + Send> ProgressReporter for LineProgressReporter<W> {
 
         let _ = write!(
             state.writer,
-            "\rdirs: {} safetensors: {} zip: {} extracted: {}",
+            "\rdirs: {} models: {} zip: {} extracted: {}",
             stats.directories_scanned,
-            stats.safetensors_directories,
+            stats.model_directories,
             stats.zip_files_checked,
             stats.extracted
         );
         let _ = state.writer.flush();
-        state.last_stats = *stats;
+        state.last_stats = stats.clone();
     }
 
-    fn on_invalid_zip(&self, zip_path: &Path, reason: &str) {
+    fn on_invalid_zip(&self, zip_path: &Path, error: &InvalidArchive) {
         let mut state = match self.state.lock() {
             Ok(state) => state,
             Err(err) => err.into_inner(),
@@ -253,11 +1389,97 @@ impl<W: Write + Send> ProgressReporter for LineProgressReporter<W> {
             state.writer,
             "\ninvalid zip: {} ({})\n",
             zip_path.display(),
+            error
+        );
+        let _ = state.writer.flush();
+    }
+
+    fn on_password_required(&self, zip_path: &Path, error: &InvalidArchive) {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(err) => err.into_inner(),
+        };
+
+        let _ = write!(
+            state.writer,
+            "\npassword required: {} ({})\n",
+            zip_path.display(),
+            error
+        );
+        let _ = state.writer.flush();
+    }
+
+    fn on_invalid_header(&self, model_path: &Path, reason: &str) {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(err) => err.into_inner(),
+        };
+
+        let _ = write!(
+            state.writer,
+            "\ninvalid header: {} ({})\n",
+            model_path.display(),
             reason
         );
         let _ = state.writer.flush();
     }
 
+    fn on_invalid_json(&self, zip_path: &Path, target_path: &Path, reason: &str) {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(err) => err.into_inner(),
+        };
+
+        let _ = write!(
+            state.writer,
+            "\ninvalid json: {} from {} ({})\n",
+            target_path.display(),
+            zip_path.display(),
+            reason
+        );
+        let _ = state.writer.flush();
+    }
+
+    fn on_entry_too_large(&self, zip_path: &Path, target_path: &Path, reason: &str) {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(err) => err.into_inner(),
+        };
+
+        let _ = write!(
+            state.writer,
+            "\nentry too large: {} from {} ({})\n",
+            target_path.display(),
+            zip_path.display(),
+            reason
+        );
+        let _ = state.writer.flush();
+    }
+
+    fn on_schema_violation(&self, path: &Path, violations: &[SchemaViolation]) {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(err) => err.into_inner(),
+        };
+
+        let _ = write!(
+            state.writer,
+            "\n{}\n",
+            format_schema_violation(path, violations)
+        );
+        let _ = state.writer.flush();
+    }
+
+    fn on_error(&self, path: &Path, error: &ExtractError) {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(err) => err.into_inner(),
+        };
+
+        let _ = write!(state.writer, "\nerror: {} ({})\n", path.display(), error);
+        let _ = state.writer.flush();
+    }
+
     fn on_finish(&self, stats: &ExtractStats) {
         self.on_update(stats);
         let mut state = match self.state.lock() {
@@ -269,6 +1491,19 @@ impl<W: Write + Send> ProgressReporter for LineProgressReporter<W> {
     }
 }
 
+fn format_schema_violation(path: &Path, violations: &[SchemaViolation]) -> String {
+    let mut message = format!("schema violation: {}", path.display());
+    for violation in violations {
+        let pointer = if violation.pointer.is_empty() {
+            "/"
+        } else {
+            violation.pointer.as_str()
+        };
+        message.push_str(&format!("\n  {}: {}", pointer, violation.message));
+    }
+    message
+}
+
 fn format_stats(stats: &ExtractStats) -> String {
     format!(
         "dirs: {} zip: {} extracted: {}",
@@ -280,18 +1515,52 @@ fn format_stats(stats: &ExtractStats) -> String {
 
 #[cfg(test)]
 mod tests {
-    use super::format_stats;
-    use crate::domain::ExtractStats;
+    use std::path::PathBuf;
+
+    use super::{format_plan_table, format_stats};
+    use crate::domain::{ExtractPlan, ExtractStats, PlannedAction, PlannedArchive, PlannedEntry};
 
     #[test]
     fn format_stats_shows_dirs_zip_extracted_only() {
         let stats = ExtractStats {
             directories_scanned: 1,
-            safetensors_directories: 99,
+            model_directories: 99,
             zip_files_checked: 2,
             extracted: 3,
+            ..ExtractStats::default()
         };
 
         assert_eq!(format_stats(&stats), "dirs: 1 zip: 2 extracted: 3");
     }
+
+    #[test]
+    fn format_plan_table_aligns_columns_and_lists_empty_archives() {
+        let plan = ExtractPlan {
+            archives: vec![
+                PlannedArchive {
+                    path: PathBuf::from("a/model.zip"),
+                    entries: vec![PlannedEntry {
+                        pattern: "model_info.json".to_string(),
+                        entry: "model_info.json".to_string(),
+                        target: PathBuf::from("a/model_info.json"),
+                        action: PlannedAction::Replace,
+                        detail: Some("backup".to_string()),
+                    }],
+                    invalid: None,
+                },
+                PlannedArchive {
+                    path: PathBuf::from("b/other.zip"),
+                    entries: Vec::new(),
+                    invalid: None,
+                },
+            ],
+        };
+
+        assert_eq!(
+            format_plan_table(&plan),
+            "ACTION   ARCHIVE      ENTRY            TARGET             DETAIL\n\
+             replace  a/model.zip  model_info.json  a/model_info.json  backup\n\
+             none     b/other.zip  -                -                  no matching entry\n"
+        );
+    }
 }
@@ -0,0 +1,383 @@
+use std::cell::OnceCell;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use flate2::read::GzDecoder;
+
+use super::timestamp;
+
+const MAGIC_LEN: usize = 16;
+const EXTENDED_TIMESTAMP_ID: u16 = 0x5455;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ArchiveError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("{0}")]
+    Format(String),
+    #[error("{0}")]
+    Encrypted(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    pub name: String,
+    pub size: Option<u64>,
+    // 圧縮率の制限に使う。分からない形式では None
+    pub compressed_size: Option<u64>,
+    pub modified: Option<SystemTime>,
+    pub is_dir: bool,
+}
+
+pub struct ArchiveProbe<'a> {
+    path: &'a Path,
+    file_name: String,
+    magic: OnceCell<Vec<u8>>,
+}
+
+impl<'a> ArchiveProbe<'a> {
+    pub fn new(path: &'a Path) -> Self {
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+
+        Self {
+            path,
+            file_name,
+            magic: OnceCell::new(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        self.path
+    }
+
+    pub fn has_suffix(&self, suffix: &str) -> bool {
+        self.file_name.ends_with(&suffix.to_ascii_lowercase())
+    }
+
+    // 拡張子だけで判定できる形式のためにファイルを開かずに済むよう遅延読み込みにしている
+    pub fn magic(&self) -> &[u8] {
+        self.magic.get_or_init(|| {
+            let mut buffer = Vec::with_capacity(MAGIC_LEN);
+            if let Ok(file) = fs::File::open(self.path) {
+                let _ = file.take(MAGIC_LEN as u64).read_to_end(&mut buffer);
+            }
+            buffer
+        })
+    }
+}
+
+pub trait ArchiveReader: Send + Sync {
+    fn name(&self) -> &str;
+    fn detect(&self, probe: &ArchiveProbe) -> bool;
+    fn open(&self, path: &Path) -> Result<Box<dyn Archive>, ArchiveError>;
+}
+
+pub trait Archive {
+    fn entries(&mut self) -> Result<Vec<ArchiveEntry>, ArchiveError>;
+    fn open_entry(&mut self, index: usize) -> Result<Box<dyn Read + '_>, ArchiveError>;
+
+    // 暗号化に対応しない形式はパスワードを使わずに開く
+    fn open_encrypted_entry(
+        &mut self,
+        index: usize,
+        _password: &str,
+    ) -> Result<Box<dyn Read + '_>, ArchiveError> {
+        self.open_entry(index)
+    }
+}
+
+#[derive(Clone)]
+pub struct ArchiveRegistry {
+    readers: Vec<Arc<dyn ArchiveReader>>,
+}
+
+impl ArchiveRegistry {
+    pub fn empty() -> Self {
+        Self {
+            readers: Vec::new(),
+        }
+    }
+
+    pub fn new() -> Self {
+        let mut registry = Self::empty();
+        registry.register(ZipReader);
+        registry.register(TarReader);
+        registry.register(GzReader);
+        registry
+    }
+
+    // 後から登録したリーダーを優先し、組み込み形式の挙動を差し替えられるようにする
+    pub fn register<R: ArchiveReader + 'static>(&mut self, reader: R) {
+        self.readers.insert(0, Arc::new(reader));
+    }
+
+    pub fn detect(&self, path: &Path) -> Option<&dyn ArchiveReader> {
+        let probe = ArchiveProbe::new(path);
+        self.readers
+            .iter()
+            .find(|reader| reader.detect(&probe))
+            .map(|reader| reader.as_ref())
+    }
+}
+
+impl Default for ArchiveRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct ZipReader;
+
+impl ArchiveReader for ZipReader {
+    fn name(&self) -> &str {
+        "zip"
+    }
+
+    fn detect(&self, probe: &ArchiveProbe) -> bool {
+        probe.has_suffix(".zip")
+    }
+
+    fn open(&self, path: &Path) -> Result<Box<dyn Archive>, ArchiveError> {
+        let file = fs::File::open(path)?;
+        let archive = zip::ZipArchive::new(file).map_err(zip_error)?;
+        Ok(Box::new(ZipArchiveHandle { archive }))
+    }
+}
+
+// 拡張フィールド 0x5455 の先頭はフラグで、bit 0 が立っていれば続く 4 バイトが更新日時 (UNIX 時刻)
+fn extended_timestamp(mut extra: &[u8]) -> Option<SystemTime> {
+    while let [id_low, id_high, len_low, len_high, rest @ ..] = extra {
+        let len = usize::from(u16::from_le_bytes([*len_low, *len_high]));
+        let data = rest.get(..len)?;
+        if u16::from_le_bytes([*id_low, *id_high]) == EXTENDED_TIMESTAMP_ID {
+            let (&flags, time) = data.split_first()?;
+            if flags & 1 == 0 {
+                return None;
+            }
+            let seconds = i32::from_le_bytes(time.get(..4)?.try_into().ok()?);
+            return timestamp::from_unix_seconds(u64::try_from(seconds).ok()?);
+        }
+        extra = &rest[len..];
+    }
+    None
+}
+
+struct ZipArchiveHandle {
+    archive: zip::ZipArchive<fs::File>,
+}
+
+impl Archive for ZipArchiveHandle {
+    fn entries(&mut self) -> Result<Vec<ArchiveEntry>, ArchiveError> {
+        let mut entries = Vec::with_capacity(self.archive.len());
+
+        for index in 0..self.archive.len() {
+            let entry = self.archive.by_index_raw(index).map_err(zip_error)?;
+            // 拡張タイムスタンプは UTC なのでそのまま使い、無ければ DOS 形式の現地時刻から求める
+            let modified = extended_timestamp(entry.extra_data()).or_else(|| {
+                let modified = entry.last_modified();
+                timestamp::from_local_civil(
+                    i64::from(modified.year()),
+                    u32::from(modified.month()),
+                    u32::from(modified.day()),
+                    u32::from(modified.hour()),
+                    u32::from(modified.minute()),
+                    u32::from(modified.second()),
+                )
+            });
+            entries.push(ArchiveEntry {
+                name: entry.name().to_string(),
+                size: Some(entry.size()),
+                compressed_size: Some(entry.compressed_size()),
+                modified,
+                is_dir: entry.is_dir(),
+            });
+        }
+
+        Ok(entries)
+    }
+
+    fn open_entry(&mut self, index: usize) -> Result<Box<dyn Read + '_>, ArchiveError> {
+        let entry = self.archive.by_index(index).map_err(zip_error)?;
+        Ok(Box::new(entry))
+    }
+
+    // パスワードの確認をすり抜けることがあるので、正しいかどうかは呼び出し側が読み切れるかで確かめる
+    fn open_encrypted_entry(
+        &mut self,
+        index: usize,
+        password: &str,
+    ) -> Result<Box<dyn Read + '_>, ArchiveError> {
+        match self
+            .archive
+            .by_index_decrypt(index, password.as_bytes())
+            .map_err(zip_error)?
+        {
+            Ok(entry) => Ok(Box::new(entry)),
+            Err(err) => Err(ArchiveError::Encrypted(err.to_string())),
+        }
+    }
+}
+
+fn zip_error(err: zip::result::ZipError) -> ArchiveError {
+    match err {
+        zip::result::ZipError::Io(err) => ArchiveError::Io(err),
+        zip::result::ZipError::UnsupportedArchive(message)
+            if message == zip::result::ZipError::PASSWORD_REQUIRED =>
+        {
+            ArchiveError::Encrypted(message.to_string())
+        }
+        err => ArchiveError::Format(err.to_string()),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum TarCompression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+pub struct TarReader;
+
+impl TarReader {
+    fn compression(probe: &ArchiveProbe) -> Option<TarCompression> {
+        if probe.has_suffix(".tar") {
+            Some(TarCompression::None)
+        } else if probe.has_suffix(".tar.gz") || probe.has_suffix(".tgz") {
+            Some(TarCompression::Gzip)
+        } else if probe.has_suffix(".tar.zst") || probe.has_suffix(".tzst") {
+            Some(TarCompression::Zstd)
+        } else {
+            None
+        }
+    }
+}
+
+impl ArchiveReader for TarReader {
+    fn name(&self) -> &str {
+        "tar"
+    }
+
+    fn detect(&self, probe: &ArchiveProbe) -> bool {
+        Self::compression(probe).is_some()
+    }
+
+    fn open(&self, path: &Path) -> Result<Box<dyn Archive>, ArchiveError> {
+        let compression = Self::compression(&ArchiveProbe::new(path))
+            .ok_or_else(|| ArchiveError::Format("not a tar archive".to_string()))?;
+
+        Ok(Box::new(TarArchiveHandle {
+            path: path.to_path_buf(),
+            compression,
+            archive: None,
+        }))
+    }
+}
+
+struct TarArchiveHandle {
+    path: PathBuf,
+    compression: TarCompression,
+    archive: Option<tar::Archive<Box<dyn Read>>>,
+}
+
+impl TarArchiveHandle {
+    // tar はランダムアクセスできないため、呼び出しごとに先頭からストリームを開き直す
+    fn reopen(&mut self) -> Result<&mut tar::Archive<Box<dyn Read>>, ArchiveError> {
+        let file = fs::File::open(&self.path)?;
+        let stream: Box<dyn Read> = match self.compression {
+            TarCompression::None => Box::new(file),
+            TarCompression::Gzip => Box::new(GzDecoder::new(file)),
+            TarCompression::Zstd => Box::new(zstd::Decoder::new(file)?),
+        };
+
+        Ok(self.archive.insert(tar::Archive::new(stream)))
+    }
+}
+
+impl Archive for TarArchiveHandle {
+    fn entries(&mut self) -> Result<Vec<ArchiveEntry>, ArchiveError> {
+        let archive = self.reopen()?;
+        let mut entries = Vec::new();
+
+        for entry in archive.entries()? {
+            let entry = entry?;
+            let header = entry.header();
+            entries.push(ArchiveEntry {
+                name: entry.path()?.to_string_lossy().into_owned(),
+                size: header.size().ok(),
+                compressed_size: None,
+                modified: header.mtime().ok().and_then(timestamp::from_unix_seconds),
+                is_dir: !header.entry_type().is_file(),
+            });
+        }
+
+        Ok(entries)
+    }
+
+    fn open_entry(&mut self, index: usize) -> Result<Box<dyn Read + '_>, ArchiveError> {
+        let archive = self.reopen()?;
+        let entry = archive
+            .entries()?
+            .nth(index)
+            .ok_or_else(|| ArchiveError::Format(format!("entry {index} not found")))??;
+
+        Ok(Box::new(entry))
+    }
+}
+
+pub struct GzReader;
+
+impl ArchiveReader for GzReader {
+    fn name(&self) -> &str {
+        "gz"
+    }
+
+    fn detect(&self, probe: &ArchiveProbe) -> bool {
+        probe.has_suffix(".gz") && !probe.has_suffix(".tar.gz")
+    }
+
+    fn open(&self, path: &Path) -> Result<Box<dyn Archive>, ArchiveError> {
+        // 単体の .gz は中身のファイル名を拡張子を除いた名前とみなす
+        let entry_name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .ok_or_else(|| ArchiveError::Format("missing file name".to_string()))?;
+
+        Ok(Box::new(GzArchiveHandle {
+            path: path.to_path_buf(),
+            entry_name,
+        }))
+    }
+}
+
+struct GzArchiveHandle {
+    path: PathBuf,
+    entry_name: String,
+}
+
+impl Archive for GzArchiveHandle {
+    fn entries(&mut self) -> Result<Vec<ArchiveEntry>, ArchiveError> {
+        Ok(vec![ArchiveEntry {
+            name: self.entry_name.clone(),
+            size: None,
+            compressed_size: fs::metadata(&self.path).ok().map(|metadata| metadata.len()),
+            modified: None,
+            is_dir: false,
+        }])
+    }
+
+    fn open_entry(&mut self, index: usize) -> Result<Box<dyn Read + '_>, ArchiveError> {
+        if index != 0 {
+            return Err(ArchiveError::Format(format!("entry {index} not found")));
+        }
+
+        let file = fs::File::open(&self.path)?;
+        Ok(Box::new(GzDecoder::new(file)))
+    }
+}
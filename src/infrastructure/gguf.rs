@@ -0,0 +1,245 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::{Number, Value};
+
+const MAGIC: &[u8; 4] = b"GGUF";
+// 壊れたファイルで巨大な確保をしないよう、ヘッダー内の長さと件数に上限を設ける
+const MAX_STRING_LEN: u64 = 16 * 1024 * 1024;
+const MAX_KV_COUNT: u64 = 1_000_000;
+const MAX_ARRAY_LEN: u64 = 64 * 1024 * 1024;
+// 語彙リストのような巨大な配列はサイドカーに全件出さず長さだけを残す
+const INLINE_ARRAY_LIMIT: u64 = 64;
+
+#[derive(Debug, thiserror::Error)]
+pub enum GgufError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("not a gguf file")]
+    BadMagic,
+    #[error("unsupported gguf version {0}")]
+    UnsupportedVersion(u32),
+    #[error("{0}")]
+    Format(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GgufMetadata {
+    pub version: u32,
+    pub tensor_count: u64,
+    pub architecture: Option<String>,
+    pub context_length: Option<u64>,
+    pub quantization: Option<String>,
+    pub tokenizer: GgufTokenizer,
+    pub metadata: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct GgufTokenizer {
+    pub model: Option<String>,
+    pub vocab_size: Option<u64>,
+    pub bos_token_id: Option<u64>,
+    pub eos_token_id: Option<u64>,
+}
+
+pub fn read_metadata(path: &Path) -> Result<GgufMetadata, GgufError> {
+    // キー/値セクションだけを順に読み、テンソルデータには触れない
+    let mut reader = GgufReader {
+        inner: BufReader::new(fs::File::open(path)?),
+        version: 0,
+    };
+
+    let mut magic = [0u8; 4];
+    reader.inner.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(GgufError::BadMagic);
+    }
+
+    let version = reader.read_u32()?;
+    if !(1..=3).contains(&version) {
+        return Err(GgufError::UnsupportedVersion(version));
+    }
+    reader.version = version;
+
+    let tensor_count = reader.read_count()?;
+    let kv_count = reader.read_count()?;
+    if kv_count > MAX_KV_COUNT {
+        return Err(GgufError::Format(format!("too many metadata entries: {kv_count}")));
+    }
+
+    let mut metadata = BTreeMap::new();
+    for _ in 0..kv_count {
+        let key = reader.read_string()?;
+        let value_type = reader.read_u32()?;
+        let value = reader.read_value(value_type)?;
+        metadata.insert(key, value);
+    }
+
+    let text = |key: &str| metadata.get(key).and_then(Value::as_str).map(str::to_string);
+    let number = |key: &str| metadata.get(key).and_then(Value::as_u64);
+    let architecture = text("general.architecture");
+    let context_length = architecture
+        .as_deref()
+        .and_then(|arch| number(&format!("{arch}.context_length")));
+    let quantization = number("general.file_type").map(file_type_name);
+    let tokenizer = GgufTokenizer {
+        model: text("tokenizer.ggml.model"),
+        vocab_size: metadata
+            .get("tokenizer.ggml.tokens")
+            .and_then(array_length),
+        bos_token_id: number("tokenizer.ggml.bos_token_id"),
+        eos_token_id: number("tokenizer.ggml.eos_token_id"),
+    };
+
+    Ok(GgufMetadata {
+        version,
+        tensor_count,
+        architecture,
+        context_length,
+        quantization,
+        tokenizer,
+        metadata,
+    })
+}
+
+fn array_length(value: &Value) -> Option<u64> {
+    match value {
+        Value::Array(items) => Some(items.len() as u64),
+        Value::Object(summary) => summary.get("length").and_then(Value::as_u64),
+        _ => None,
+    }
+}
+
+// llama.cpp の LLAMA_FTYPE_* に対応する名前
+fn file_type_name(file_type: u64) -> String {
+    let name = match file_type {
+        0 => "F32",
+        1 => "F16",
+        2 => "Q4_0",
+        3 => "Q4_1",
+        7 => "Q8_0",
+        8 => "Q5_0",
+        9 => "Q5_1",
+        10 => "Q2_K",
+        11 => "Q3_K_S",
+        12 => "Q3_K_M",
+        13 => "Q3_K_L",
+        14 => "Q4_K_S",
+        15 => "Q4_K_M",
+        16 => "Q5_K_S",
+        17 => "Q5_K_M",
+        18 => "Q6_K",
+        19 => "IQ2_XXS",
+        20 => "IQ2_XS",
+        21 => "Q2_K_S",
+        22 => "IQ3_XS",
+        23 => "IQ3_XXS",
+        24 => "IQ1_S",
+        25 => "IQ4_NL",
+        26 => "IQ3_S",
+        27 => "IQ3_M",
+        28 => "IQ2_S",
+        29 => "IQ2_M",
+        30 => "IQ4_XS",
+        31 => "IQ1_M",
+        32 => "BF16",
+        other => return format!("unknown({other})"),
+    };
+    name.to_string()
+}
+
+struct GgufReader<R: Read> {
+    inner: R,
+    version: u32,
+}
+
+impl<R: Read> GgufReader<R> {
+    fn read_bytes<const N: usize>(&mut self) -> Result<[u8; N], GgufError> {
+        let mut buffer = [0u8; N];
+        self.inner.read_exact(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, GgufError> {
+        Ok(u32::from_le_bytes(self.read_bytes()?))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, GgufError> {
+        Ok(u64::from_le_bytes(self.read_bytes()?))
+    }
+
+    // v1 だけは件数と長さが 32bit
+    fn read_count(&mut self) -> Result<u64, GgufError> {
+        if self.version == 1 {
+            Ok(u64::from(self.read_u32()?))
+        } else {
+            self.read_u64()
+        }
+    }
+
+    fn read_string(&mut self) -> Result<String, GgufError> {
+        let len = self.read_count()?;
+        if len > MAX_STRING_LEN {
+            return Err(GgufError::Format(format!("string length {len} exceeds limit")));
+        }
+
+        let mut buffer = vec![0u8; len as usize];
+        self.inner.read_exact(&mut buffer)?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+
+    fn read_value(&mut self, value_type: u32) -> Result<Value, GgufError> {
+        let value = match value_type {
+            0 => Value::from(self.read_bytes::<1>()?[0]),
+            1 => Value::from(i8::from_le_bytes(self.read_bytes()?)),
+            2 => Value::from(u16::from_le_bytes(self.read_bytes()?)),
+            3 => Value::from(i16::from_le_bytes(self.read_bytes()?)),
+            4 => Value::from(self.read_u32()?),
+            5 => Value::from(i32::from_le_bytes(self.read_bytes()?)),
+            6 => float_value(f64::from(f32::from_le_bytes(self.read_bytes()?))),
+            7 => Value::Bool(self.read_bytes::<1>()?[0] != 0),
+            8 => Value::String(self.read_string()?),
+            9 => self.read_array()?,
+            10 => Value::from(self.read_u64()?),
+            11 => Value::from(i64::from_le_bytes(self.read_bytes()?)),
+            12 => float_value(f64::from_le_bytes(self.read_bytes()?)),
+            other => {
+                return Err(GgufError::Format(format!("unknown value type {other}")));
+            }
+        };
+
+        Ok(value)
+    }
+
+    fn read_array(&mut self) -> Result<Value, GgufError> {
+        let item_type = self.read_u32()?;
+        let len = self.read_count()?;
+        if len > MAX_ARRAY_LEN {
+            return Err(GgufError::Format(format!("array length {len} exceeds limit")));
+        }
+
+        let mut items = Vec::new();
+        for _ in 0..len {
+            let item = self.read_value(item_type)?;
+            if len <= INLINE_ARRAY_LIMIT {
+                items.push(item);
+            }
+        }
+
+        if len <= INLINE_ARRAY_LIMIT {
+            Ok(Value::Array(items))
+        } else {
+            let mut summary = serde_json::Map::new();
+            summary.insert("type".to_string(), Value::from("array"));
+            summary.insert("length".to_string(), Value::from(len));
+            Ok(Value::Object(summary))
+        }
+    }
+}
+
+fn float_value(value: f64) -> Value {
+    Number::from_f64(value).map_or(Value::Null, Value::Number)
+}
@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use serde_json::Value;
+
+// safetensors の仕様上ヘッダーは 100MB を超えないため、壊れた長さで巨大な確保をしないよう制限する
+const MAX_HEADER_LEN: u64 = 100 * 1024 * 1024;
+const METADATA_KEY: &str = "__metadata__";
+
+#[derive(Debug, thiserror::Error)]
+pub enum SafetensorsError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("header length {0} exceeds limit")]
+    HeaderTooLarge(u64),
+    #[error("invalid header json: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    Format(String),
+}
+
+pub fn read_metadata(path: &Path) -> Result<Option<BTreeMap<String, String>>, SafetensorsError> {
+    let mut file = fs::File::open(path)?;
+
+    let mut length_bytes = [0u8; 8];
+    file.read_exact(&mut length_bytes)?;
+    let header_len = u64::from_le_bytes(length_bytes);
+    if header_len > MAX_HEADER_LEN {
+        return Err(SafetensorsError::HeaderTooLarge(header_len));
+    }
+
+    // テンソル本体は読まずヘッダー部分だけを取り出す
+    let mut header = Vec::with_capacity(header_len as usize);
+    file.take(header_len).read_to_end(&mut header)?;
+    if header.len() as u64 != header_len {
+        return Err(SafetensorsError::Format("truncated header".to_string()));
+    }
+
+    let header: Value = serde_json::from_slice(&header)?;
+    let Value::Object(mut header) = header else {
+        return Err(SafetensorsError::Format("header is not an object".to_string()));
+    };
+
+    match header.remove(METADATA_KEY) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::Object(metadata)) => {
+            let metadata = metadata
+                .into_iter()
+                .map(|(key, value)| match value {
+                    Value::String(value) => Ok((key, value)),
+                    other => Err(SafetensorsError::Format(format!(
+                        "metadata value for {key} is not a string: {other}"
+                    ))),
+                })
+                .collect::<Result<BTreeMap<_, _>, _>>()?;
+            Ok(Some(metadata))
+        }
+        Some(_) => Err(SafetensorsError::Format(format!(
+            "{METADATA_KEY} is not an object"
+        ))),
+    }
+}
+
+// メタデータの値には JSON 文字列が埋め込まれていることが多いので、解釈できるものは構造化して出力する
+pub fn metadata_to_json(metadata: &BTreeMap<String, String>) -> Value {
+    let object = metadata
+        .iter()
+        .map(|(key, value)| {
+            let trimmed = value.trim_start();
+            let parsed = if trimmed.starts_with('{') || trimmed.starts_with('[') {
+                serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.clone()))
+            } else {
+                Value::String(value.clone())
+            };
+            (key.clone(), parsed)
+        })
+        .collect();
+
+    Value::Object(object)
+}
@@ -0,0 +1,283 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection};
+
+use crate::application::{ArchiveRecord, CatalogSink, ExtractError, ZipEntryOutcome};
+use crate::domain::{CatalogRecord, ExtractStats};
+
+// 実行ごとに同じパスの行を上書きし、最後に見た実行の ID を残す
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    root TEXT NOT NULL,
+    version TEXT NOT NULL,
+    started_at INTEGER NOT NULL,
+    finished_at INTEGER,
+    directories_scanned INTEGER,
+    model_directories INTEGER,
+    zip_files_checked INTEGER,
+    extracted INTEGER,
+    skipped INTEGER,
+    invalid_json INTEGER,
+    schema_violations INTEGER,
+    generated_from_header INTEGER
+);
+CREATE TABLE IF NOT EXISTS zips (
+    path TEXT PRIMARY KEY,
+    directory TEXT NOT NULL,
+    status TEXT NOT NULL,
+    reason TEXT,
+    bytes_written INTEGER NOT NULL,
+    duration_ms INTEGER NOT NULL,
+    last_run_id INTEGER NOT NULL REFERENCES runs(id)
+);
+CREATE TABLE IF NOT EXISTS extracted_files (
+    path TEXT NOT NULL,
+    sha256 TEXT NOT NULL,
+    zip_path TEXT NOT NULL,
+    pattern TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    first_run_id INTEGER NOT NULL REFERENCES runs(id),
+    last_run_id INTEGER NOT NULL REFERENCES runs(id),
+    PRIMARY KEY (path, sha256)
+);
+CREATE TABLE IF NOT EXISTS models (
+    path TEXT PRIMARY KEY,
+    directory TEXT NOT NULL,
+    file_name TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    model_info_path TEXT NOT NULL,
+    model_info_sha256 TEXT NOT NULL,
+    model_info TEXT,
+    base_model TEXT,
+    source TEXT NOT NULL,
+    source_archive TEXT,
+    last_run_id INTEGER NOT NULL REFERENCES runs(id)
+);
+CREATE INDEX IF NOT EXISTS models_base_model ON models(base_model);
+CREATE INDEX IF NOT EXISTS models_model_info_sha256 ON models(model_info_sha256);
+";
+
+pub struct SqliteCatalog {
+    path: PathBuf,
+    state: Mutex<SqliteState>,
+}
+
+struct SqliteState {
+    connection: Connection,
+    run_id: Option<i64>,
+}
+
+impl SqliteCatalog {
+    pub fn open(path: &Path) -> Result<Self, ExtractError> {
+        let open_error = |err| database_error(path, err);
+        let connection = Connection::open(path).map_err(open_error)?;
+        connection
+            .execute_batch("PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL;")
+            .map_err(open_error)?;
+        connection.execute_batch(SCHEMA).map_err(open_error)?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            state: Mutex::new(SqliteState {
+                connection,
+                run_id: None,
+            }),
+        })
+    }
+
+    fn with_run<T>(
+        &self,
+        f: impl FnOnce(&Connection, i64) -> rusqlite::Result<T>,
+    ) -> Result<T, ExtractError> {
+        let state = match self.state.lock() {
+            Ok(state) => state,
+            Err(err) => err.into_inner(),
+        };
+        let run_id = state.run_id.ok_or_else(|| ExtractError::Catalog {
+            path: self.path.clone(),
+            source: "run not started".into(),
+        })?;
+        f(&state.connection, run_id).map_err(|err| database_error(&self.path, err))
+    }
+}
+
+impl CatalogSink for SqliteCatalog {
+    fn on_start(&self, root: &Path) -> Result<(), ExtractError> {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(err) => err.into_inner(),
+        };
+        state
+            .connection
+            .execute(
+                "INSERT INTO runs (root, version, started_at) VALUES (?1, ?2, ?3)",
+                params![
+                    root.to_string_lossy(),
+                    env!("CARGO_PKG_VERSION"),
+                    unix_seconds()
+                ],
+            )
+            .map_err(|err| database_error(&self.path, err))?;
+        state.run_id = Some(state.connection.last_insert_rowid());
+        Ok(())
+    }
+
+    fn on_archive(&self, record: &ArchiveRecord) -> Result<(), ExtractError> {
+        self.with_run(|connection, run_id| {
+            let transaction = connection.unchecked_transaction()?;
+            transaction.execute(
+                "INSERT INTO zips (path, directory, status, reason, bytes_written, duration_ms, last_run_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                 ON CONFLICT(path) DO UPDATE SET
+                     directory = excluded.directory,
+                     status = excluded.status,
+                     reason = excluded.reason,
+                     bytes_written = excluded.bytes_written,
+                     duration_ms = excluded.duration_ms,
+                     last_run_id = excluded.last_run_id",
+                params![
+                    record.path.to_string_lossy(),
+                    record.directory.to_string_lossy(),
+                    record.status(),
+                    record.reason(),
+                    record.bytes_written() as i64,
+                    record.duration.as_millis() as i64,
+                    run_id
+                ],
+            )?;
+
+            for outcome in &record.outcomes {
+                let ZipEntryOutcome::Extracted {
+                    pattern,
+                    path,
+                    bytes,
+                    sha256,
+                    ..
+                } = outcome
+                else {
+                    continue;
+                };
+                transaction.execute(
+                    "INSERT INTO extracted_files (path, sha256, zip_path, pattern, size, first_run_id, last_run_id)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)
+                     ON CONFLICT(path, sha256) DO UPDATE SET
+                         zip_path = excluded.zip_path,
+                         pattern = excluded.pattern,
+                         size = excluded.size,
+                         last_run_id = excluded.last_run_id",
+                    params![
+                        path.to_string_lossy(),
+                        sha256,
+                        record.path.to_string_lossy(),
+                        pattern,
+                        *bytes as i64,
+                        run_id
+                    ],
+                )?;
+            }
+
+            transaction.commit()
+        })
+    }
+
+    fn on_record(&self, record: &CatalogRecord) -> Result<(), ExtractError> {
+        let model_info = record.model_info.as_ref().map(ToString::to_string);
+        let base_model = record
+            .model_info
+            .as_ref()
+            .and_then(|info| info.get("baseModel"))
+            .and_then(|value| value.as_str());
+        let source = serde_json::to_value(record.source)
+            .ok()
+            .and_then(|value| value.as_str().map(str::to_string))
+            .unwrap_or_default();
+
+        self.with_run(|connection, run_id| {
+            let transaction = connection.unchecked_transaction()?;
+            for model in &record.models {
+                transaction.execute(
+                    "INSERT INTO models (path, directory, file_name, size, model_info_path, model_info_sha256,
+                                         model_info, base_model, source, source_archive, last_run_id)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+                     ON CONFLICT(path) DO UPDATE SET
+                         directory = excluded.directory,
+                         file_name = excluded.file_name,
+                         size = excluded.size,
+                         model_info_path = excluded.model_info_path,
+                         model_info_sha256 = excluded.model_info_sha256,
+                         model_info = excluded.model_info,
+                         base_model = excluded.base_model,
+                         source = excluded.source,
+                         source_archive = excluded.source_archive,
+                         last_run_id = excluded.last_run_id",
+                    params![
+                        record.directory.join(&model.file_name).to_string_lossy(),
+                        record.directory.to_string_lossy(),
+                        model.file_name,
+                        model.size as i64,
+                        record.model_info_path.to_string_lossy(),
+                        record.model_info_sha256,
+                        model_info,
+                        base_model,
+                        source,
+                        record
+                            .source_archive
+                            .as_ref()
+                            .map(|path| path.to_string_lossy()),
+                        run_id
+                    ],
+                )?;
+            }
+            transaction.commit()
+        })
+    }
+
+    fn on_finish(&self, stats: &ExtractStats) -> Result<(), ExtractError> {
+        let skipped = stats.skipped_existing + stats.skipped_unchanged + stats.skipped_not_newer;
+        self.with_run(|connection, run_id| {
+            connection.execute(
+                "UPDATE runs SET
+                     finished_at = ?1,
+                     directories_scanned = ?2,
+                     model_directories = ?3,
+                     zip_files_checked = ?4,
+                     extracted = ?5,
+                     skipped = ?6,
+                     invalid_json = ?7,
+                     schema_violations = ?8,
+                     generated_from_header = ?9
+                 WHERE id = ?10",
+                params![
+                    unix_seconds(),
+                    stats.directories_scanned as i64,
+                    stats.model_directories as i64,
+                    stats.zip_files_checked as i64,
+                    stats.extracted as i64,
+                    skipped as i64,
+                    stats.invalid_json as i64,
+                    stats.schema_violations as i64,
+                    stats.generated_from_header as i64,
+                    run_id
+                ],
+            )?;
+            Ok(())
+        })
+    }
+}
+
+fn unix_seconds() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+fn database_error(path: &Path, err: rusqlite::Error) -> ExtractError {
+    ExtractError::Catalog {
+        path: path.to_path_buf(),
+        source: err.into(),
+    }
+}
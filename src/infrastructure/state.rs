@@ -0,0 +1,197 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use super::{output_error, read_error};
+use crate::application::{ArchiveRecord, CatalogSink, ExtractError, ZipEntryOutcome};
+use crate::domain::ExtractOptions;
+
+pub const DEFAULT_STATE_FILE_NAME: &str = ".extract-model-info-state.json";
+const STATE_VERSION: u32 = 2;
+
+// 対象パターンや出力先が変わると前回の「見つからなかった」「展開済み」は当てにならないので、丸ごと捨てる。
+// 上書きの方針も、前回「既にある」「新しくない」で残したものを今回は上書きすべき場合があるので含める
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct StateKey {
+    version: u32,
+    entries: Vec<String>,
+    output_root: Option<PathBuf>,
+    overwrite: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct ArchiveState {
+    size: u64,
+    modified: SystemTime,
+    outcome: String,
+    targets: Vec<PathBuf>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StateFile {
+    key: StateKey,
+    archives: BTreeMap<PathBuf, ArchiveState>,
+}
+
+pub struct StateCache {
+    path: PathBuf,
+    key: StateKey,
+    previous: BTreeMap<PathBuf, ArchiveState>,
+    state: Mutex<StateCacheState>,
+}
+
+#[derive(Default)]
+struct StateCacheState {
+    archives: BTreeMap<PathBuf, ArchiveState>,
+    seen: BTreeSet<PathBuf>,
+}
+
+impl StateCache {
+    // 状態ファイルが無い、壊れている、条件が違う場合は空のキャッシュから始める
+    pub fn open(path: &Path, options: &ExtractOptions) -> Result<Self, ExtractError> {
+        let key = StateKey {
+            version: STATE_VERSION,
+            entries: options.entries.patterns().map(str::to_string).collect(),
+            output_root: options.output_root.clone(),
+            overwrite: options.overwrite.as_str().to_string(),
+        };
+        let previous = match fs::read(path) {
+            Ok(contents) => serde_json::from_slice::<StateFile>(&contents)
+                .ok()
+                .filter(|state| state.key == key)
+                .map(|state| state.archives)
+                .unwrap_or_default(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(read_error(path, err)),
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            key,
+            previous,
+            state: Mutex::new(StateCacheState::default()),
+        })
+    }
+
+    pub fn default_path(root: &Path) -> PathBuf {
+        root.join(DEFAULT_STATE_FILE_NAME)
+    }
+
+    // 今回見つからなかったアーカイブは削除されたものとして状態ファイルから落とす
+    pub fn finish(self) -> Result<usize, ExtractError> {
+        let state = self.state.into_inner().unwrap_or_else(|err| err.into_inner());
+        let mut archives = state.archives;
+        for path in state.seen {
+            if let Some(archive) = self.previous.get(&path) {
+                archives.entry(path).or_insert_with(|| archive.clone());
+            }
+        }
+
+        let count = archives.len();
+        let contents = serde_json::to_vec_pretty(&StateFile {
+            key: self.key,
+            archives,
+        })
+        .map_err(|err| output_error(&self.path, err))?;
+
+        // 書き込み途中で止まっても前回の状態ファイルが壊れないよう、一時ファイルから置き換える
+        let parent = self
+            .path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        // --output-root の下に置く場合、何も展開しなければまだディレクトリが無いことがある
+        fs::create_dir_all(parent).map_err(|err| output_error(parent, err))?;
+        let mut staged =
+            tempfile::NamedTempFile::new_in(parent).map_err(|err| output_error(parent, err))?;
+        staged.write_all(&contents).map_err(|err| output_error(&self.path, err))?;
+        staged
+            .persist(&self.path)
+            .map_err(|err| output_error(&self.path, err.error))?;
+
+        Ok(count)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, StateCacheState> {
+        match self.state.lock() {
+            Ok(state) => state,
+            Err(err) => err.into_inner(),
+        }
+    }
+}
+
+impl CatalogSink for StateCache {
+    fn is_unchanged(&self, zip_path: &Path) -> Result<bool, ExtractError> {
+        let Some(previous) = self.previous.get(zip_path) else {
+            return Ok(false);
+        };
+        let Some((size, modified)) = stamp(zip_path) else {
+            return Ok(false);
+        };
+
+        // 展開先を消された場合は、アーカイブが同じでも展開し直す
+        let unchanged = previous.size == size
+            && previous.modified == modified
+            && previous.targets.iter().all(|target| target.exists());
+        if unchanged {
+            self.lock().seen.insert(zip_path.to_path_buf());
+        }
+        Ok(unchanged)
+    }
+
+    fn on_archive(&self, record: &ArchiveRecord) -> Result<(), ExtractError> {
+        // 壊れたアーカイブや不正な JSON、制限を超えたエントリは毎回報告されるよう、成功したものだけを覚える。
+        // パスワードは状態のキーに含めないので、後から渡されたときに開き直せるよう暗号化も覚えない。
+        // キャッシュで飛ばしたものは前回の状態をそのまま引き継ぐ
+        if record.outcomes.contains(&ZipEntryOutcome::Cached) {
+            return Ok(());
+        }
+        let failed = record.outcomes.iter().any(|outcome| {
+            matches!(
+                outcome,
+                ZipEntryOutcome::InvalidZip(_)
+                    | ZipEntryOutcome::PasswordRequired(_)
+                    | ZipEntryOutcome::InvalidJson { .. }
+                    | ZipEntryOutcome::TooLarge { .. }
+                    | ZipEntryOutcome::WriteFailed { .. }
+            )
+        });
+        if failed {
+            return Ok(());
+        }
+        let Some((size, modified)) = stamp(&record.path) else {
+            return Ok(());
+        };
+
+        let targets = record
+            .outcomes
+            .iter()
+            .filter_map(|outcome| match outcome {
+                ZipEntryOutcome::Extracted { path, .. } | ZipEntryOutcome::Skipped { path, .. } => {
+                    Some(path.clone())
+                }
+                _ => None,
+            })
+            .collect();
+        self.lock().archives.insert(
+            record.path.clone(),
+            ArchiveState {
+                size,
+                modified,
+                outcome: record.status().to_string(),
+                targets,
+            },
+        );
+        Ok(())
+    }
+}
+
+fn stamp(path: &Path) -> Option<(u64, SystemTime)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
@@ -0,0 +1,133 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use walkdir::WalkDir;
+
+use crate::application::ExtractError;
+
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_secs(2);
+
+// 一度の debounce でまとめた変更。監視のエラーは監視を止めずに呼び出し側へ渡す
+#[derive(Debug, Default)]
+pub struct ChangeBatch {
+    pub directories: Vec<PathBuf>,
+    // キューのあふれなどでイベントを取りこぼしたので、ルート全体を走査し直す必要がある
+    pub rescan: bool,
+    pub errors: Vec<ExtractError>,
+}
+
+impl ChangeBatch {
+    fn is_empty(&self) -> bool {
+        self.directories.is_empty() && !self.rescan && self.errors.is_empty()
+    }
+}
+
+pub struct ChangeWatcher {
+    root: PathBuf,
+    // 破棄すると監視が止まるので、使わなくても保持しておく
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    debounce: Duration,
+}
+
+impl ChangeWatcher {
+    pub fn new(root: &Path, debounce: Duration) -> Result<Self, ExtractError> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher =
+            notify::recommended_watcher(sender).map_err(|err| watch_error(root, err))?;
+        watcher
+            .watch(root, RecursiveMode::Recursive)
+            .map_err(|err| watch_error(root, err))?;
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            _watcher: watcher,
+            events,
+            debounce,
+        })
+    }
+
+    // 書き込み中のファイルは更新イベントが続くので、debounce の間イベントが途切れるまで待ってからまとめて返す
+    pub fn next_batch(
+        &self,
+        is_relevant: &dyn Fn(&Path) -> bool,
+    ) -> Result<ChangeBatch, ExtractError> {
+        let mut batch = ChangeBatch::default();
+        let mut directories = BTreeSet::new();
+        let mut quiet_since = Instant::now();
+
+        loop {
+            let event = if batch.is_empty() && directories.is_empty() {
+                self.events.recv().map_err(|_| self.closed_error())?
+            } else {
+                let remaining = self.debounce.saturating_sub(quiet_since.elapsed());
+                match self.events.recv_timeout(remaining) {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => return Err(self.closed_error()),
+                }
+            };
+
+            let event = match event {
+                Ok(event) => event,
+                Err(err) => {
+                    batch.errors.push(watch_error(&self.root, err));
+                    quiet_since = Instant::now();
+                    continue;
+                }
+            };
+            if event.need_rescan() {
+                batch.rescan = true;
+                quiet_since = Instant::now();
+                continue;
+            }
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                continue;
+            }
+            let is_added = matches!(
+                event.kind,
+                EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_))
+            );
+            for path in &event.paths {
+                // ディレクトリごと置かれた (mv された) 場合は中のファイルのイベントが来ないので、中を歩いて探す
+                let files = if is_added && path.is_dir() {
+                    WalkDir::new(path)
+                        .into_iter()
+                        .filter_map(Result::ok)
+                        .filter(|entry| entry.file_type().is_file())
+                        .map(walkdir::DirEntry::into_path)
+                        .collect()
+                } else {
+                    vec![path.clone()]
+                };
+                for file in files.iter().filter(|file| is_relevant(file)) {
+                    if let Some(parent) = file.parent() {
+                        directories.insert(parent.to_path_buf());
+                        quiet_since = Instant::now();
+                    }
+                }
+            }
+        }
+
+        batch.directories = directories.into_iter().collect();
+        Ok(batch)
+    }
+
+    fn closed_error(&self) -> ExtractError {
+        ExtractError::Watch {
+            path: self.root.clone(),
+            source: "file watcher stopped".into(),
+        }
+    }
+}
+
+fn watch_error(root: &Path, err: notify::Error) -> ExtractError {
+    ExtractError::Watch {
+        path: root.to_path_buf(),
+        source: err.into(),
+    }
+}
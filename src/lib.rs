@@ -3,9 +3,25 @@ pub mod domain;
 pub mod infrastructure;
 
 pub use crate::application::{
-    extract_model_info, ExtractError, FilePorts, ProgressReporter, ZipEntryOutcome,
+    extract_model_info, extract_model_info_in_directories, extract_model_info_with_catalog,
+    extract_model_info_with_options, ArchiveRecord, BoxError, CatalogSink, ExtractError, FilePorts,
+    HeaderMetadata, HeaderOutcome, InvalidArchive, InvalidArchiveKind, ProgressReporter,
+    SkipReason, ZipEntryOutcome,
+};
+pub use crate::domain::{
+    CatalogModel, CatalogRecord, CatalogSource, DirectoryFilter, EntryLimit, EntryLimits,
+    EntryPatterns, ErrorPolicy,
+    ExtractOptions, ExtractPlan, ExtractReport, ExtractStats, ModelExtensions, ModelInfoSchema, OverwritePolicy,
+    PlannedAction, PlannedArchive, PlannedEntry, ReportedError, RunSummary, SchemaViolation,
+    TrainingMetadata, TrainingRecord, ZipPasswords, DEFAULT_EXCLUDES, DEFAULT_MAX_COMPRESSION_RATIO,
+    DEFAULT_MAX_ENTRY_SIZE, DEFAULT_MODEL_EXTENSIONS, GGUF_EXTENSION, GGUF_SIDECAR_SUFFIX, MODEL_INFO_FILE_NAME, SAFETENSORS_EXTENSION,
+    TRAINING_SIDECAR_SUFFIX, ZIP_PASSWORD_FILE_NAME,
 };
-pub use crate::domain::{ExtractStats, MODEL_INFO_FILE_NAME};
 pub use crate::infrastructure::{
-    FsPorts, IndicatifProgressReporter, LineProgressReporter, NoProgressReporter,
+    format_plan_table, load_schema, write_training_summary, Archive, ArchiveEntry, ArchiveError,
+    ArchiveProbe, ArchiveReader, ArchiveRegistry, CatalogWriter, ChangeBatch, ChangeWatcher,
+    ErrorCollectingReporter, FsPorts, IndicatifProgressReporter, LineProgressReporter,
+    NoProgressReporter, RunReportWriter, StateCache, DEFAULT_DEBOUNCE, DEFAULT_STATE_FILE_NAME,
 };
+#[cfg(feature = "sqlite")]
+pub use crate::infrastructure::SqliteCatalog;
@@ -1,38 +1,510 @@
 use std::error::Error;
-use std::path::PathBuf;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
 
-use clap::Parser;
-use extract_model_info_json::{extract_model_info, FsPorts, IndicatifProgressReporter};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use extract_model_info_json::{
+    extract_model_info_in_directories, extract_model_info_with_catalog,
+    extract_model_info_with_options, format_plan_table, load_schema, write_training_summary,
+    CatalogSink, CatalogWriter, ChangeWatcher, DirectoryFilter, EntryLimits, EntryPatterns,
+    ErrorCollectingReporter, ErrorPolicy, ExtractError, ExtractOptions, ExtractStats, FilePorts, FsPorts,
+    IndicatifProgressReporter, LineProgressReporter, ModelExtensions, OverwritePolicy,
+    ProgressReporter, RunReportWriter, RunSummary, StateCache, ZipPasswords, DEFAULT_DEBOUNCE, DEFAULT_EXCLUDES,
+    DEFAULT_MAX_COMPRESSION_RATIO, DEFAULT_MAX_ENTRY_SIZE, DEFAULT_MODEL_EXTENSIONS,
+    MODEL_INFO_FILE_NAME,
+};
 
 #[derive(Parser)]
-#[command(version, about)]
+#[command(
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(value_name = "ROOT_DIR", required = true)]
+    root_dir: Option<PathBuf>,
+
+    #[command(flatten)]
+    extract: ExtractArgs,
+
+    /// Write a per-model training sidecar and a tree-wide summary (.csv or .json) from ss_* metadata
+    #[arg(long, value_name = "PATH")]
+    training_report: Option<PathBuf>,
+
+    /// Write a catalog of every model_info.json with its models (.jsonl streams JSON Lines, otherwise a JSON array)
+    #[arg(long, value_name = "PATH", conflicts_with = "dry_run")]
+    catalog: Option<PathBuf>,
+
+    /// Write one record per examined archive (outcome, reason, bytes written, duration) as .csv or JSON
+    #[arg(long, value_name = "PATH", conflicts_with = "dry_run")]
+    report: Option<PathBuf>,
+
+    /// Skip archives whose size and mtime did not change since the previous run (state kept in .extract-model-info-state.json under --output-root, or else ROOT_DIR)
+    #[arg(long, conflicts_with = "dry_run")]
+    incremental: bool,
+
+    /// Keep the --incremental state in this file instead (implies --incremental)
+    #[arg(long, value_name = "PATH", conflicts_with = "dry_run")]
+    state_file: Option<PathBuf>,
+
+    /// Upsert models, archives, extracted files and run history into this SQLite database
+    #[cfg(feature = "sqlite")]
+    #[arg(long, value_name = "PATH", conflicts_with = "dry_run")]
+    catalog_db: Option<PathBuf>,
+
+    /// Print what would be extracted without writing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Output format of the --dry-run plan
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        value_enum,
+        default_value_t = PlanFormat::Table,
+        requires = "dry_run"
+    )]
+    plan_format: PlanFormat,
+
+    /// Output format of the final summary on stdout
+    #[arg(long, value_name = "FORMAT", value_enum, default_value_t = SummaryFormat::Text)]
+    format: SummaryFormat,
+
+    /// Exit with status 2 when any archive, entry, header or directory could not be processed
+    #[arg(long)]
+    strict: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Scan once, then keep extracting as archives and model files are created or modified
+    Watch(WatchArgs),
+}
+
+#[derive(Args)]
+struct WatchArgs {
     #[arg(value_name = "ROOT_DIR")]
     root_dir: PathBuf,
+
+    #[command(flatten)]
+    extract: ExtractArgs,
+
+    /// Milliseconds without further changes before a directory is processed
+    #[arg(long, value_name = "MS", default_value_t = DEFAULT_DEBOUNCE.as_millis() as u64)]
+    debounce_ms: u64,
+}
+
+// 一回きりの走査と watch で共通の、展開の仕方に関する指定
+#[derive(Args)]
+struct ExtractArgs {
+    /// Entry name or glob pattern to extract (repeatable)
+    #[arg(long = "entry", value_name = "PATTERN", default_value = MODEL_INFO_FILE_NAME)]
+    entries: Vec<String>,
+
+    /// What to do when the extracted file already exists
+    #[arg(long, value_name = "POLICY", value_enum, default_value_t = OverwriteArg::Always)]
+    overwrite: OverwriteArg,
+
+    /// What to do when a directory cannot be read or listed, or an extracted file cannot be written
+    #[arg(long, value_name = "POLICY", value_enum, default_value_t = ErrorPolicyArg::Continue)]
+    on_error: ErrorPolicyArg,
+
+    /// Write extracted files and sidecars under this directory, mirroring the layout below ROOT_DIR
+    #[arg(long, value_name = "DIR")]
+    output_root: Option<PathBuf>,
+
+    /// Only scan directories matching this glob, relative to ROOT_DIR, and everything below them (repeatable)
+    #[arg(long = "include", value_name = "GLOB")]
+    includes: Vec<String>,
+
+    /// Skip directories matching this glob, relative to ROOT_DIR, with everything below them (repeatable)
+    #[arg(long = "exclude", value_name = "GLOB")]
+    excludes: Vec<String>,
+
+    /// Do not skip .git, .cache, trash and snapshot directories by default
+    #[arg(long)]
+    no_default_excludes: bool,
+
+    /// Password to try on encrypted archives (repeatable)
+    #[arg(
+        long = "password",
+        value_name = "PASSWORD",
+        env = "EXTRACT_MODEL_INFO_PASSWORD",
+        hide_env_values = true
+    )]
+    passwords: Vec<String>,
+
+    /// File with passwords to try on encrypted archives, one per line
+    #[arg(long, value_name = "PATH")]
+    password_file: Option<PathBuf>,
+
+    /// Stop extracting an entry larger than this many bytes (0 = no limit)
+    #[arg(long, value_name = "BYTES", default_value_t = DEFAULT_MAX_ENTRY_SIZE)]
+    max_entry_size: u64,
+
+    /// Stop extracting an entry that expands more than this many times its compressed size (0 = no limit)
+    #[arg(long, value_name = "RATIO", default_value_t = DEFAULT_MAX_COMPRESSION_RATIO)]
+    max_compression_ratio: u64,
+
+    /// Model file extension that marks a directory for extraction (repeatable)
+    #[arg(long = "model-ext", value_name = "EXT", default_values = DEFAULT_MODEL_EXTENSIONS)]
+    model_extensions: Vec<String>,
+
+    /// Generate model_info.json from the safetensors header metadata when no archive provides one
+    #[arg(long)]
+    from_header: bool,
+
+    /// Write a <model>.gguf.json sidecar with the header metadata of every .gguf file
+    #[arg(long)]
+    gguf_metadata: bool,
+
+    /// Validate extracted model_info.json files against this JSON Schema
+    #[arg(long, value_name = "PATH")]
+    schema: Option<PathBuf>,
+
+    /// Also validate model_info.json files that already exist (requires --schema)
+    #[arg(long, requires = "schema")]
+    validate_existing: bool,
+}
+
+impl ExtractArgs {
+    fn options(&self) -> Result<ExtractOptions, Box<dyn Error>> {
+        Ok(ExtractOptions {
+            entries: EntryPatterns::new(&self.entries)?,
+            overwrite: self.overwrite.into(),
+            on_error: self.on_error.into(),
+            passwords: self.passwords()?,
+            limits: EntryLimits {
+                max_size: Some(self.max_entry_size).filter(|size| *size > 0),
+                max_ratio: Some(self.max_compression_ratio).filter(|ratio| *ratio > 0),
+            },
+            output_root: self.output_root.clone(),
+            directories: self.directory_filter()?,
+            model_extensions: ModelExtensions::new(&self.model_extensions),
+            header_fallback: self.from_header,
+            gguf_metadata: self.gguf_metadata,
+            schema: self.schema.as_deref().map(load_schema).transpose()?,
+            validate_existing: self.validate_existing,
+            ..ExtractOptions::default()
+        })
+    }
+
+    // アーカイブごとの .zip-password は展開時に読むので、ここではコマンドラインの指定だけをまとめる
+    fn passwords(&self) -> Result<ZipPasswords, ExtractError> {
+        let mut passwords = ZipPasswords::new(&self.passwords);
+        if let Some(path) = &self.password_file {
+            let contents = fs::read_to_string(path).map_err(|source| ExtractError::Read {
+                path: path.clone(),
+                source,
+            })?;
+            passwords.extend(ZipPasswords::from_lines(&contents));
+        }
+        Ok(passwords)
+    }
+
+    fn directory_filter(&self) -> Result<DirectoryFilter, Box<dyn Error>> {
+        let mut excludes = Vec::new();
+        if !self.no_default_excludes {
+            excludes.extend(DEFAULT_EXCLUDES.iter().map(|pattern| pattern.to_string()));
+        }
+        excludes.extend(self.excludes.iter().cloned());
+
+        Ok(DirectoryFilter::new(&self.includes, &excludes)?)
+    }
+}
+
+// 終了コードは README に記載しているので、値を変えるときは合わせて更新する
+const EXIT_FATAL: u8 = 1;
+const EXIT_PARTIAL_FAILURE: u8 = 2;
+const EXIT_NOTHING_FOUND: u8 = 3;
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SummaryFormat {
+    Text,
+    Json,
+    Yaml,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum PlanFormat {
+    Table,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ErrorPolicyArg {
+    /// Report the directory, count it in the summary and keep scanning the rest of the tree
+    Continue,
+    /// Stop the whole run at the first unreadable directory
+    FailFast,
+}
+
+impl From<ErrorPolicyArg> for ErrorPolicy {
+    fn from(arg: ErrorPolicyArg) -> Self {
+        match arg {
+            ErrorPolicyArg::Continue => ErrorPolicy::Continue,
+            ErrorPolicyArg::FailFast => ErrorPolicy::FailFast,
+        }
+    }
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
+#[derive(Clone, Copy, ValueEnum)]
+enum OverwriteArg {
+    /// Always replace the existing file
+    Always,
+    /// Keep the existing file
+    Never,
+    /// Replace only when the contents differ
+    IfDifferent,
+    /// Replace only when the archive entry is newer than the existing file
+    IfNewer,
+    /// Rename the existing file to <name>.<timestamp>.bak before replacing it
+    Backup,
+}
+
+impl From<OverwriteArg> for OverwritePolicy {
+    fn from(arg: OverwriteArg) -> Self {
+        match arg {
+            OverwriteArg::Always => OverwritePolicy::Always,
+            OverwriteArg::Never => OverwritePolicy::Never,
+            OverwriteArg::IfDifferent => OverwritePolicy::IfDifferent,
+            OverwriteArg::IfNewer => OverwritePolicy::IfNewer,
+            OverwriteArg::Backup => OverwritePolicy::Backup,
+        }
+    }
+}
+
+fn main() -> ExitCode {
     let cli = Cli::parse();
+    let result = match cli.command {
+        Some(Command::Watch(args)) => watch(args),
+        None => run(cli),
+    };
+    match result {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            ExitCode::from(EXIT_FATAL)
+        }
+    }
+}
 
-    if !cli.root_dir.exists() {
-        return Err(format!("root not found: {}", cli.root_dir.display()).into());
+fn check_root(root_dir: &Path) -> Result<(), Box<dyn Error>> {
+    if !root_dir.exists() {
+        return Err(format!("root not found: {}", root_dir.display()).into());
     }
 
-    if !cli.root_dir.is_dir() {
-        return Err(format!("not a directory: {}", cli.root_dir.display()).into());
+    if !root_dir.is_dir() {
+        return Err(format!("not a directory: {}", root_dir.display()).into());
     }
 
+    Ok(())
+}
+
+fn run(cli: Cli) -> Result<ExitCode, Box<dyn Error>> {
+    let root_dir = cli.root_dir.ok_or("missing ROOT_DIR")?;
+    check_root(&root_dir)?;
+
+    let options = ExtractOptions {
+        training_report: cli.training_report.is_some(),
+        dry_run: cli.dry_run,
+        ..cli.extract.options()?
+    };
+
+    let started = Instant::now();
     let ports = FsPorts::new();
-    let progress = IndicatifProgressReporter::new();
-    let stats = extract_model_info(&ports, &progress, &cli.root_dir)?;
+    let indicatif = IndicatifProgressReporter::new();
+    let progress = ErrorCollectingReporter::new(&indicatif);
+    let catalog = cli.catalog.as_deref().map(CatalogWriter::create).transpose()?;
+    #[cfg(feature = "sqlite")]
+    let catalog_db = cli
+        .catalog_db
+        .as_deref()
+        .map(extract_model_info_json::SqliteCatalog::open)
+        .transpose()?;
+
+    let run_report = cli.report.as_deref().map(RunReportWriter::new);
+    // 読み取り専用の元ツリーには書けないことがあるので、出力先を分けていればそちらに置く
+    let state_path = match cli.state_file {
+        Some(path) => Some(path),
+        None => cli.incremental.then(|| {
+            StateCache::default_path(options.output_root.as_deref().unwrap_or(&root_dir))
+        }),
+    };
+    let state = state_path
+        .as_deref()
+        .map(|path| StateCache::open(path, &options))
+        .transpose()?;
+
+    let mut sinks: Vec<&dyn CatalogSink> = Vec::new();
+    if let Some(catalog) = &catalog {
+        sinks.push(catalog);
+    }
+    if let Some(run_report) = &run_report {
+        sinks.push(run_report);
+    }
+    if let Some(state) = &state {
+        sinks.push(state);
+    }
+    #[cfg(feature = "sqlite")]
+    if let Some(catalog_db) = &catalog_db {
+        sinks.push(catalog_db);
+    }
 
+    let report = extract_model_info_with_catalog(
+        &ports,
+        &progress,
+        &root_dir,
+        &options,
+        (!sinks.is_empty()).then_some(&sinks as &dyn CatalogSink),
+    )?;
+    if let Some(catalog) = catalog {
+        catalog.finish()?;
+    }
+    if let Some(run_report) = run_report {
+        run_report.finish()?;
+    }
+    // 状態を保存できなくても今回の処理は終わっているので、報告だけして集計と終了コードはそのまま返す
+    if let Some(state) = state
+        && let Err(err) = state.finish()
+    {
+        let path = err.path().map(Path::to_path_buf).unwrap_or_default();
+        progress.on_error(&path, &err);
+    }
+
+    if let Some(plan) = &report.plan {
+        match cli.plan_format {
+            PlanFormat::Table => print!("{}", format_plan_table(plan)),
+            PlanFormat::Json => println!("{}", serde_json::to_string_pretty(plan)?),
+        }
+        return Ok(exit_code(&report.stats, cli.strict));
+    }
+
+    if let Some(path) = &cli.training_report {
+        write_training_summary(path, &report.training)?;
+    }
+
+    let summary = RunSummary {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        root: root_dir,
+        elapsed_seconds: started.elapsed().as_secs_f64(),
+        stats: report.stats,
+        errors: progress.into_errors(),
+    };
+    match cli.format {
+        SummaryFormat::Text => {
+            print_text_summary(&summary.stats);
+            for error in &summary.errors {
+                println!("{}: {} ({})", error.kind, error.path.display(), error.message);
+            }
+        }
+        SummaryFormat::Json => println!("{}", serde_json::to_string_pretty(&summary)?),
+        SummaryFormat::Yaml => print!("{}", serde_yaml::to_string(&summary)?),
+    }
+
+    Ok(exit_code(&summary.stats, cli.strict))
+}
+
+// 監視は初回の走査より先に始め、走査中に置かれたファイルも取りこぼさないようにする
+fn watch(args: WatchArgs) -> Result<ExitCode, Box<dyn Error>> {
+    check_root(&args.root_dir)?;
+    let options = args.extract.options()?;
+    let ports = FsPorts::new();
+    let progress = LineProgressReporter::new();
+    let watcher = ChangeWatcher::new(&args.root_dir, Duration::from_millis(args.debounce_ms))?;
+
+    let report = extract_model_info_with_options(&ports, &progress, &args.root_dir, &options)?;
+    print_text_summary(&report.stats);
+
+    let is_watched = |path: &Path| {
+        let directory = path.parent().unwrap_or(path);
+        let relative = directory.strip_prefix(&args.root_dir).unwrap_or(directory);
+        if !options.directories.matches(relative) {
+            return false;
+        }
+        let is_model = path
+            .extension()
+            .and_then(OsStr::to_str)
+            .is_some_and(|ext| options.model_extensions.find_match(ext).is_some());
+        is_model || ports.is_archive(path)
+    };
+    // 監視のエラーや一回分の処理の失敗では止めず、報告して次の変更を待つ
+    loop {
+        let batch = watcher.next_batch(&is_watched)?;
+        for err in &batch.errors {
+            eprintln!("Error: {err}");
+        }
+        let result = if batch.rescan {
+            extract_model_info_with_options(&ports, &progress, &args.root_dir, &options)
+        } else if batch.directories.is_empty() {
+            continue;
+        } else {
+            extract_model_info_in_directories(
+                &ports,
+                &progress,
+                &args.root_dir,
+                &batch.directories,
+                &options,
+                None,
+            )
+        };
+        match result {
+            Ok(report) => print_text_summary(&report.stats),
+            Err(err) => eprintln!("Error: {err}"),
+        }
+    }
+}
+
+fn exit_code(stats: &ExtractStats, strict: bool) -> ExitCode {
+    if strict && stats.failures() > 0 {
+        ExitCode::from(EXIT_PARTIAL_FAILURE)
+    } else if stats.model_directories == 0 {
+        ExitCode::from(EXIT_NOTHING_FOUND)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn print_text_summary(stats: &ExtractStats) {
+    let by_extension = stats
+        .model_directories_by_extension
+        .iter()
+        .map(|(extension, count)| format!("{extension}={count}"))
+        .collect::<Vec<_>>()
+        .join(",");
     println!(
-        "directories: {} safetensors_dirs: {} zip_checked: {} extracted: {}",
+        "directories: {} model_dirs: {} ({}) zip_checked: {} invalid_zips: {} password_required: {} extracted: {} skipped: {} (exists={},unchanged={},not_newer={},cached={}) backed_up: {} invalid_json: {} too_large: {} invalid_headers: {} unreadable_dirs: {} write_failures: {} schema_violations: {} from_header: {} training_reports: {} gguf_metadata: {}",
         stats.directories_scanned,
-        stats.safetensors_directories,
+        stats.model_directories,
+        by_extension,
         stats.zip_files_checked,
-        stats.extracted
+        stats.invalid_zips,
+        stats.password_required,
+        stats.extracted,
+        stats.skipped_existing
+            + stats.skipped_unchanged
+            + stats.skipped_not_newer
+            + stats.skipped_cached,
+        stats.skipped_existing,
+        stats.skipped_unchanged,
+        stats.skipped_not_newer,
+        stats.skipped_cached,
+        stats.backed_up,
+        stats.invalid_json,
+        stats.entries_too_large,
+        stats.invalid_headers,
+        stats.unreadable_directories,
+        stats.write_failures,
+        stats.schema_violations,
+        stats.generated_from_header,
+        stats.training_reports,
+        stats.gguf_metadata
     );
-
-    Ok(())
 }
@@ -0,0 +1,103 @@
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use extract_model_info_json::{
+    extract_model_info, Archive, ArchiveEntry, ArchiveError, ArchiveProbe, ArchiveReader,
+    ArchiveRegistry, FilePorts, FsPorts, NoProgressReporter, MODEL_INFO_FILE_NAME,
+};
+
+const MAGIC: &[u8] = b"MIB1\n";
+
+// 1 行目がマジック、以降は "名前\t内容" の行が並ぶだけの社内形式を想定
+struct LineBundleReader;
+
+impl ArchiveReader for LineBundleReader {
+    fn name(&self) -> &str {
+        "line-bundle"
+    }
+
+    fn detect(&self, probe: &ArchiveProbe) -> bool {
+        probe.magic().starts_with(MAGIC)
+    }
+
+    fn open(&self, path: &Path) -> Result<Box<dyn Archive>, ArchiveError> {
+        let contents = fs::read_to_string(path)?;
+        let entries = contents
+            .lines()
+            .skip(1)
+            .map(|line| {
+                line.split_once('\t')
+                    .map(|(name, body)| (name.to_string(), body.to_string()))
+                    .ok_or_else(|| ArchiveError::Format(format!("malformed line: {line}")))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Box::new(LineBundle { entries }))
+    }
+}
+
+struct LineBundle {
+    entries: Vec<(String, String)>,
+}
+
+impl Archive for LineBundle {
+    fn entries(&mut self) -> Result<Vec<ArchiveEntry>, ArchiveError> {
+        Ok(self
+            .entries
+            .iter()
+            .map(|(name, body)| ArchiveEntry {
+                name: name.clone(),
+                size: Some(body.len() as u64),
+                compressed_size: None,
+                modified: None,
+                is_dir: false,
+            })
+            .collect())
+    }
+
+    fn open_entry(&mut self, index: usize) -> Result<Box<dyn Read + '_>, ArchiveError> {
+        let (_, body) = &self.entries[index];
+        Ok(Box::new(Cursor::new(body.as_bytes())))
+    }
+}
+
+#[test]
+fn default_registry_detects_builtin_formats() {
+    let registry = ArchiveRegistry::new();
+
+    let detected = |name: &str| registry.detect(Path::new(name)).map(|reader| reader.name().to_string());
+
+    assert_eq!(detected("a/model.zip").as_deref(), Some("zip"));
+    assert_eq!(detected("a/model.TAR.GZ").as_deref(), Some("tar"));
+    assert_eq!(detected("a/model.tzst").as_deref(), Some("tar"));
+    assert_eq!(detected("a/model_info.json.gz").as_deref(), Some("gz"));
+    assert_eq!(detected("a/model.safetensors"), None);
+}
+
+#[test]
+fn extracts_through_registered_custom_reader() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let model_dir = temp_dir.path().join("model");
+    fs::create_dir_all(&model_dir)?;
+
+    fs::write(model_dir.join("model.safetensors"), b"")?;
+    let mut bundle = MAGIC.to_vec();
+    bundle.extend_from_slice(b"meta/model_info.json\t{\"custom\": true}\n");
+    fs::write(model_dir.join("model.bundle"), bundle)?;
+
+    let mut registry = ArchiveRegistry::new();
+    registry.register(LineBundleReader);
+    let ports = FsPorts::with_archive_registry(registry);
+    assert!(ports.is_archive(&model_dir.join("model.bundle")));
+    assert!(!ports.is_archive(&model_dir.join("model.safetensors")));
+
+    let progress = NoProgressReporter::new();
+    let stats = extract_model_info(&ports, &progress, temp_dir.path())?;
+
+    let extracted = fs::read_to_string(model_dir.join(MODEL_INFO_FILE_NAME))?;
+    assert_eq!(extracted, "{\"custom\": true}");
+    assert_eq!(stats.extracted, 1);
+
+    Ok(())
+}
@@ -0,0 +1,92 @@
+#![cfg(feature = "sqlite")]
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use extract_model_info_json::{
+    extract_model_info_with_catalog, ExtractOptions, FsPorts, NoProgressReporter, SqliteCatalog,
+    MODEL_INFO_FILE_NAME,
+};
+
+fn create_zip(path: &Path, entries: Vec<(&str, &str)>) -> Result<(), Box<dyn std::error::Error>> {
+    let file = fs::File::create(path)?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options = zip::write::FileOptions::default();
+
+    for (name, contents) in entries {
+        zip.start_file(name, options)?;
+        zip.write_all(contents.as_bytes())?;
+    }
+
+    zip.finish()?;
+    Ok(())
+}
+
+#[test]
+fn upserts_models_archives_and_runs() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let root = temp_dir.path().join("models");
+    let sdxl_dir = root.join("sdxl");
+    let sd15_dir = root.join("sd15");
+    fs::create_dir_all(&sdxl_dir)?;
+    fs::create_dir_all(&sd15_dir)?;
+
+    fs::write(sdxl_dir.join("a.safetensors"), b"1234")?;
+    create_zip(
+        &sdxl_dir.join("a.zip"),
+        vec![(MODEL_INFO_FILE_NAME, "{\"baseModel\": \"SDXL\"}")],
+    )?;
+    fs::write(sd15_dir.join("b.safetensors"), b"12")?;
+    fs::write(sd15_dir.join("broken.zip"), b"not a zip")?;
+    fs::write(sd15_dir.join(MODEL_INFO_FILE_NAME), "{\"baseModel\": \"SD 1.5\"}")?;
+
+    let database_path = temp_dir.path().join("catalog.sqlite");
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    for _ in 0..2 {
+        let catalog = SqliteCatalog::open(&database_path)?;
+        extract_model_info_with_catalog(
+            &ports,
+            &progress,
+            &root,
+            &ExtractOptions::default(),
+            Some(&catalog),
+        )?;
+    }
+
+    let connection = rusqlite::Connection::open(&database_path)?;
+    let runs: i64 = connection.query_row(
+        "SELECT COUNT(*) FROM runs WHERE finished_at IS NOT NULL AND extracted = 1",
+        [],
+        |row| row.get(0),
+    )?;
+    let sdxl: Vec<String> = connection
+        .prepare("SELECT file_name FROM models WHERE base_model = 'SDXL'")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    let models: i64 = connection.query_row("SELECT COUNT(*) FROM models", [], |row| row.get(0))?;
+    let zips: Vec<(String, String)> = connection
+        .prepare("SELECT status, path FROM zips ORDER BY status")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+    let (extracted_files, last_run_id): (i64, i64) = connection.query_row(
+        "SELECT COUNT(*), MAX(last_run_id) FROM extracted_files",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    assert_eq!(runs, 2);
+    assert_eq!(sdxl, vec!["a.safetensors".to_string()]);
+    assert_eq!(models, 2);
+    assert_eq!(
+        zips,
+        vec![
+            ("extracted".to_string(), sdxl_dir.join("a.zip").display().to_string()),
+            ("invalid".to_string(), sd15_dir.join("broken.zip").display().to_string()),
+        ]
+    );
+    assert_eq!((extracted_files, last_run_id), (1, 2));
+
+    Ok(())
+}
@@ -1,9 +1,16 @@
 use std::fs;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
 use extract_model_info_json::{
-    extract_model_info, FsPorts, NoProgressReporter, MODEL_INFO_FILE_NAME,
+    extract_model_info, extract_model_info_in_directories, extract_model_info_with_catalog,
+    extract_model_info_with_options, load_schema, write_training_summary, CatalogSink,
+    CatalogWriter, ChangeWatcher, DirectoryFilter, EntryLimits, EntryPatterns,
+    ErrorCollectingReporter, ErrorPolicy,
+    ExtractError, ExtractOptions, FilePorts, FsPorts, HeaderMetadata, HeaderOutcome,
+    InvalidArchiveKind, ModelExtensions, NoProgressReporter, TrainingMetadata, ZipEntryOutcome, OverwritePolicy, PlannedAction, RunReportWriter, StateCache,
+    ZipPasswords, DEFAULT_EXCLUDES, MODEL_INFO_FILE_NAME, ZIP_PASSWORD_FILE_NAME,
 };
 
 fn create_zip(path: &Path, entries: Vec<(&str, &str)>) -> Result<(), Box<dyn std::error::Error>> {
@@ -20,6 +27,184 @@ fn create_zip(path: &Path, entries: Vec<(&str, &str)>) -> Result<(), Box<dyn std
     Ok(())
 }
 
+fn crc32_update(crc: u32, byte: u8) -> u32 {
+    let mut crc = crc ^ u32::from(byte);
+    for _ in 0..8 {
+        crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+    }
+    crc
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, byte| crc32_update(crc, *byte))
+}
+
+// 従来の ZipCrypto (PKWARE) の鍵の更新と暗号化・復号
+struct ZipCrypto {
+    keys: [u32; 3],
+}
+
+impl ZipCrypto {
+    fn new(password: &str) -> Self {
+        let mut crypto = Self {
+            keys: [0x1234_5678, 0x2345_6789, 0x3456_7890],
+        };
+        password.bytes().for_each(|byte| crypto.update(byte));
+        crypto
+    }
+
+    fn update(&mut self, byte: u8) {
+        self.keys[0] = crc32_update(self.keys[0], byte);
+        self.keys[1] = (self.keys[1].wrapping_add(self.keys[0] & 0xff))
+            .wrapping_mul(134_775_813)
+            .wrapping_add(1);
+        self.keys[2] = crc32_update(self.keys[2], (self.keys[1] >> 24) as u8);
+    }
+
+    fn stream_byte(&self) -> u8 {
+        let temp = (self.keys[2] | 2) as u16;
+        (temp.wrapping_mul(temp ^ 1) >> 8) as u8
+    }
+
+    fn encrypt(&mut self, byte: u8) -> u8 {
+        let cipher = byte ^ self.stream_byte();
+        self.update(byte);
+        cipher
+    }
+
+    fn decrypt(&mut self, byte: u8) -> u8 {
+        let plain = byte ^ self.stream_byte();
+        self.update(plain);
+        plain
+    }
+}
+
+// 12 バイトの暗号化ヘッダ。最後のバイトは CRC の上位バイトで、パスワードの確認に使われる
+fn zipcrypto_header(contents: &str, password: &str) -> Vec<u8> {
+    let mut plain = vec![0x5a; 11];
+    plain.push((crc32(contents.as_bytes()) >> 24) as u8);
+    let mut crypto = ZipCrypto::new(password);
+    plain.into_iter().map(|byte| crypto.encrypt(byte)).collect()
+}
+
+// zip クレートは暗号化して書き込めないので、従来の ZipCrypto で 1 エントリだけのアーカイブを組み立てる
+fn create_encrypted_zip(
+    path: &Path,
+    name: &str,
+    contents: &str,
+    password: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let data = contents.as_bytes();
+    let crc = crc32(data);
+    let mut crypto = ZipCrypto::new(password);
+    let mut plain = vec![0x5a; 11];
+    plain.push((crc >> 24) as u8);
+    plain.extend_from_slice(data);
+    let encrypted = plain
+        .into_iter()
+        .map(|byte| crypto.encrypt(byte))
+        .collect::<Vec<_>>();
+
+    // 暗号化フラグ付き、無圧縮、1980-01-01 のエントリ
+    let common = |out: &mut Vec<u8>| {
+        out.extend_from_slice(&20u16.to_le_bytes());
+        out.extend_from_slice(&1u16.to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(&33u16.to_le_bytes());
+        out.extend_from_slice(&crc.to_le_bytes());
+        out.extend_from_slice(&(encrypted.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+    };
+    let mut out = 0x0403_4b50u32.to_le_bytes().to_vec();
+    common(&mut out);
+    out.extend_from_slice(name.as_bytes());
+    out.extend_from_slice(&encrypted);
+
+    let central_offset = out.len() as u32;
+    out.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+    out.extend_from_slice(&20u16.to_le_bytes());
+    common(&mut out);
+    out.extend_from_slice(&[0; 14]);
+    out.extend_from_slice(name.as_bytes());
+    let central_size = out.len() as u32 - central_offset;
+
+    out.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+    out.extend_from_slice(&[0, 0, 0, 0, 1, 0, 1, 0]);
+    out.extend_from_slice(&central_size.to_le_bytes());
+    out.extend_from_slice(&central_offset.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+
+    fs::write(path, out)?;
+    Ok(())
+}
+
+fn create_tar<W: Write>(writer: W, entries: Vec<(&str, &str)>) -> Result<W, Box<dyn std::error::Error>> {
+    let mut builder = tar::Builder::new(writer);
+
+    for (name, contents) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, name, contents.as_bytes())?;
+    }
+
+    Ok(builder.into_inner()?)
+}
+
+fn create_safetensors(path: &Path, metadata: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let header = format!(
+        "{{\"__metadata__\": {metadata}, \"w\": {{\"dtype\": \"F32\", \"shape\": [1], \"data_offsets\": [0, 4]}}}}"
+    );
+    let mut bytes = (header.len() as u64).to_le_bytes().to_vec();
+    bytes.extend_from_slice(header.as_bytes());
+    bytes.extend_from_slice(&[0u8; 4]);
+    fs::write(path, bytes)?;
+    Ok(())
+}
+
+fn gguf_string(bytes: &mut Vec<u8>, value: &str) {
+    bytes.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(value.as_bytes());
+}
+
+fn create_gguf(path: &Path, vocab_size: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let mut bytes = b"GGUF".to_vec();
+    bytes.extend_from_slice(&3u32.to_le_bytes());
+    bytes.extend_from_slice(&0u64.to_le_bytes());
+    bytes.extend_from_slice(&5u64.to_le_bytes());
+
+    gguf_string(&mut bytes, "general.architecture");
+    bytes.extend_from_slice(&8u32.to_le_bytes());
+    gguf_string(&mut bytes, "llama");
+
+    gguf_string(&mut bytes, "llama.context_length");
+    bytes.extend_from_slice(&4u32.to_le_bytes());
+    bytes.extend_from_slice(&4096u32.to_le_bytes());
+
+    gguf_string(&mut bytes, "general.file_type");
+    bytes.extend_from_slice(&4u32.to_le_bytes());
+    bytes.extend_from_slice(&15u32.to_le_bytes());
+
+    gguf_string(&mut bytes, "tokenizer.ggml.model");
+    bytes.extend_from_slice(&8u32.to_le_bytes());
+    gguf_string(&mut bytes, "llama");
+
+    gguf_string(&mut bytes, "tokenizer.ggml.tokens");
+    bytes.extend_from_slice(&9u32.to_le_bytes());
+    bytes.extend_from_slice(&8u32.to_le_bytes());
+    bytes.extend_from_slice(&(vocab_size as u64).to_le_bytes());
+    for index in 0..vocab_size {
+        gguf_string(&mut bytes, &format!("tok{index}"));
+    }
+
+    fs::write(path, bytes)?;
+    Ok(())
+}
+
 #[test]
 fn extracts_model_info_json_from_zip_in_safetensors_dir() -> Result<(), Box<dyn std::error::Error>> {
     let temp_dir = tempfile::tempdir()?;
@@ -102,7 +287,7 @@ fn overwrites_existing_model_info_json() -> Result<(), Box<dyn std::error::Error
     fs::write(model_dir.join(MODEL_INFO_FILE_NAME), "old")?;
     create_zip(
         &model_dir.join("model.zip"),
-        vec![(MODEL_INFO_FILE_NAME, "new")],
+        vec![(MODEL_INFO_FILE_NAME, "\"new\"")],
     )?;
 
     let ports = FsPorts::new();
@@ -110,84 +295,1782 @@ fn overwrites_existing_model_info_json() -> Result<(), Box<dyn std::error::Error
     let _stats = extract_model_info(&ports, &progress, temp_dir.path())?;
 
     let extracted = fs::read_to_string(model_dir.join(MODEL_INFO_FILE_NAME))?;
-    assert_eq!(extracted, "new");
+    assert_eq!(extracted, "\"new\"");
 
     Ok(())
 }
 
 #[test]
-fn extracts_from_nested_directories() -> Result<(), Box<dyn std::error::Error>> {
+fn never_and_if_different_policies_keep_existing_files() -> Result<(), Box<dyn std::error::Error>> {
     let temp_dir = tempfile::tempdir()?;
-    let nested_dir = temp_dir.path().join("a").join("b").join("c");
-    fs::create_dir_all(&nested_dir)?;
+    let model_dir = temp_dir.path().join("model");
+    fs::create_dir_all(&model_dir)?;
 
-    fs::write(nested_dir.join("model.safetensors"), b"")?;
+    fs::write(model_dir.join("model.safetensors"), b"")?;
+    fs::write(model_dir.join(MODEL_INFO_FILE_NAME), "\"same\"")?;
     create_zip(
-        &nested_dir.join("model.zip"),
-        vec![(MODEL_INFO_FILE_NAME, "nested")],
+        &model_dir.join("model.zip"),
+        vec![(MODEL_INFO_FILE_NAME, "\"same\""), ("README.md", "new")],
     )?;
+    fs::write(model_dir.join("README.md"), "old")?;
 
     let ports = FsPorts::new();
     let progress = NoProgressReporter::new();
-    let stats = extract_model_info(&ports, &progress, temp_dir.path())?;
+    let mut options = ExtractOptions {
+        entries: EntryPatterns::new([MODEL_INFO_FILE_NAME, "README.md"])?,
+        overwrite: OverwritePolicy::Never,
+        ..ExtractOptions::default()
+    };
+    let stats =
+        extract_model_info_with_options(&ports, &progress, temp_dir.path(), &options)?.stats;
 
-    let extracted = fs::read_to_string(nested_dir.join(MODEL_INFO_FILE_NAME))?;
-    assert_eq!(extracted, "nested");
+    assert_eq!(fs::read_to_string(model_dir.join("README.md"))?, "old");
+    assert_eq!(stats.skipped_existing, 2);
+    assert_eq!(stats.extracted, 0);
+
+    options.overwrite = OverwritePolicy::IfDifferent;
+    let stats = extract_model_info_with_options(&ports, &progress, temp_dir.path(), &options)?.stats;
+
+    assert_eq!(fs::read_to_string(model_dir.join("README.md"))?, "new");
+    assert_eq!(stats.skipped_unchanged, 1);
     assert_eq!(stats.extracted, 1);
 
     Ok(())
 }
 
 #[test]
-fn reports_stats_for_scanned_directories() -> Result<(), Box<dyn std::error::Error>> {
+fn if_newer_policy_compares_entry_time_with_file_mtime() -> Result<(), Box<dyn std::error::Error>> {
     let temp_dir = tempfile::tempdir()?;
-    let dir_a = temp_dir.path().join("a");
-    let dir_b = temp_dir.path().join("b");
+    let model_dir = temp_dir.path().join("model");
+    fs::create_dir_all(&model_dir)?;
 
-    fs::create_dir_all(&dir_a)?;
-    fs::create_dir_all(&dir_b)?;
-    fs::write(dir_a.join("model.safetensors"), b"")?;
-    create_zip(
-        &dir_a.join("model.zip"),
-        vec![(MODEL_INFO_FILE_NAME, "ok")],
+    fs::write(model_dir.join("model.safetensors"), b"")?;
+    let target = model_dir.join(MODEL_INFO_FILE_NAME);
+    fs::write(&target, "\"old\"")?;
+
+    let file = fs::File::create(model_dir.join("model.zip"))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let modified = zip::DateTime::from_date_and_time(2000, 1, 1, 0, 0, 0)
+        .map_err(|_| "invalid zip timestamp")?;
+    zip.start_file(
+        MODEL_INFO_FILE_NAME,
+        zip::write::FileOptions::default().last_modified_time(modified),
     )?;
+    zip.write_all(b"\"new\"")?;
+    zip.finish()?;
 
+    let options = ExtractOptions {
+        overwrite: OverwritePolicy::IfNewer,
+        ..ExtractOptions::default()
+    };
     let ports = FsPorts::new();
     let progress = NoProgressReporter::new();
-    let stats = extract_model_info(&ports, &progress, temp_dir.path())?;
+    let stats = extract_model_info_with_options(&ports, &progress, temp_dir.path(), &options)?.stats;
 
-    assert!(stats.directories_scanned >= 2);
-    assert_eq!(stats.safetensors_directories, 1);
-    assert_eq!(stats.zip_files_checked, 1);
+    assert_eq!(fs::read_to_string(&target)?, "\"old\"");
+    assert_eq!(stats.skipped_not_newer, 1);
+
+    let old_mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(600_000_000);
+    fs::File::options().write(true).open(&target)?.set_modified(old_mtime)?;
+    let stats = extract_model_info_with_options(&ports, &progress, temp_dir.path(), &options)?.stats;
+
+    assert_eq!(fs::read_to_string(&target)?, "\"new\"");
     assert_eq!(stats.extracted, 1);
 
     Ok(())
 }
 
+// 既存ファイルの更新日時の 1 分前に作られたエントリを、if-newer で上書きしないことを確かめる
+fn skips_entry_written_shortly_before_target(
+    modified: zip::DateTime,
+    extended_time: Option<i32>,
+    target_mtime: SystemTime,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let model_dir = temp_dir.path().join("model");
+    fs::create_dir_all(&model_dir)?;
+
+    fs::write(model_dir.join("model.safetensors"), b"")?;
+    let target = model_dir.join(MODEL_INFO_FILE_NAME);
+    fs::write(&target, "\"old\"")?;
+    fs::File::options().write(true).open(&target)?.set_modified(target_mtime)?;
+
+    // zip クレートは予約済みの 0x5455 を書かせないので、仮の ID で書いてから差し替える
+    let placeholder = [0xfe, 0xca, 5, 0, 1];
+    let zip_path = model_dir.join("model.zip");
+    let mut zip = zip::ZipWriter::new(fs::File::create(&zip_path)?);
+    zip.start_file_with_extra_data(
+        MODEL_INFO_FILE_NAME,
+        zip::write::FileOptions::default().last_modified_time(modified),
+    )?;
+    if let Some(seconds) = extended_time {
+        zip.write_all(&placeholder)?;
+        zip.write_all(&seconds.to_le_bytes())?;
+    }
+    zip.end_extra_data()?;
+    zip.write_all(b"\"new\"")?;
+    zip.finish()?;
+
+    let mut bytes = fs::read(&zip_path)?;
+    let mut position = 0;
+    while let Some(offset) = bytes[position..].windows(5).position(|window| window == placeholder) {
+        bytes[position + offset..position + offset + 2].copy_from_slice(&[0x55, 0x54]);
+        position += offset + 5;
+    }
+    fs::write(&zip_path, bytes)?;
+
+    let options = ExtractOptions {
+        overwrite: OverwritePolicy::IfNewer,
+        ..ExtractOptions::default()
+    };
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    let stats = extract_model_info_with_options(&ports, &progress, temp_dir.path(), &options)?.stats;
+
+    assert_eq!(fs::read_to_string(&target)?, "\"old\"");
+    assert_eq!((stats.skipped_not_newer, stats.extracted), (1, 0));
+
+    Ok(())
+}
+
 #[test]
-fn continues_when_zip_is_invalid() -> Result<(), Box<dyn std::error::Error>> {
+fn if_newer_policy_prefers_extended_timestamp_of_zip_entry() -> Result<(), Box<dyn std::error::Error>> {
+    // 2023-11-14T22:13:20Z
+    let target_mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+    // DOS 形式の日時は UTC+9 の現地時刻で書かれたもので、UTC として読むと 9 時間新しく見える
+    let modified = zip::DateTime::from_date_and_time(2023, 11, 15, 7, 12, 20)
+        .map_err(|_| "invalid zip timestamp")?;
+
+    skips_entry_written_shortly_before_target(modified, Some(1_700_000_000 - 60), target_mtime)
+}
+
+#[cfg(unix)]
+#[test]
+fn if_newer_policy_reads_dos_time_of_zip_entry_as_local_time() -> Result<(), Box<dyn std::error::Error>> {
+    let target_mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let entry_seconds: libc::time_t = 1_700_000_000 - 60;
+
+    // SAFETY: localtime_r は渡したバッファにだけ書き込む
+    let local = unsafe {
+        let mut local: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&entry_seconds, &mut local);
+        local
+    };
+    let modified = zip::DateTime::from_date_and_time(
+        u16::try_from(local.tm_year + 1900)?,
+        u8::try_from(local.tm_mon + 1)?,
+        u8::try_from(local.tm_mday)?,
+        u8::try_from(local.tm_hour)?,
+        u8::try_from(local.tm_min)?,
+        u8::try_from(local.tm_sec)?,
+    )
+    .map_err(|_| "invalid zip timestamp")?;
+
+    skips_entry_written_shortly_before_target(modified, None, target_mtime)
+}
+
+#[cfg(unix)]
+#[test]
+fn extracted_files_follow_umask_or_keep_existing_mode() -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::PermissionsExt;
+
     let temp_dir = tempfile::tempdir()?;
-    let bad_dir = temp_dir.path().join("bad");
-    let good_dir = temp_dir.path().join("good");
+    let model_dir = temp_dir.path().join("model");
+    fs::create_dir_all(&model_dir)?;
+    fs::write(model_dir.join("model.safetensors"), b"")?;
+    create_zip(&model_dir.join("model.zip"), vec![(MODEL_INFO_FILE_NAME, "{}")])?;
 
-    fs::create_dir_all(&bad_dir)?;
-    fs::create_dir_all(&good_dir)?;
+    let mode = |path: &Path| -> std::io::Result<u32> {
+        Ok(fs::metadata(path)?.permissions().mode() & 0o777)
+    };
+    let target = model_dir.join(MODEL_INFO_FILE_NAME);
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    extract_model_info(&ports, &progress, temp_dir.path())?;
 
-    fs::write(bad_dir.join("model.safetensors"), b"")?;
-    fs::write(bad_dir.join("broken.zip"), b"not a zip")?;
+    // 普通に作ったファイルと同じく umask に従う
+    let reference = model_dir.join("reference");
+    fs::write(&reference, b"")?;
+    assert_eq!(mode(&target)?, mode(&reference)?);
 
-    fs::write(good_dir.join("model.safetensors"), b"")?;
+    // 上書きするときは既存ファイルの権限を引き継ぐ
+    fs::set_permissions(&target, fs::Permissions::from_mode(0o640))?;
+    fs::write(&target, "\"old\"")?;
+    extract_model_info(&ports, &progress, temp_dir.path())?;
+    assert_eq!(fs::read_to_string(&target)?, "{}");
+    assert_eq!(mode(&target)?, 0o640);
+
+    Ok(())
+}
+
+#[test]
+fn backup_policy_keeps_existing_file_as_backup_before_writing() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let model_dir = temp_dir.path().join("model");
+    fs::create_dir_all(&model_dir)?;
+
+    fs::write(model_dir.join("model.safetensors"), b"")?;
+    fs::write(model_dir.join(MODEL_INFO_FILE_NAME), "\"old\"")?;
     create_zip(
-        &good_dir.join("model.zip"),
-        vec![(MODEL_INFO_FILE_NAME, "ok")],
+        &model_dir.join("model.zip"),
+        vec![(MODEL_INFO_FILE_NAME, "\"new\"")],
+    )?;
+
+    let options = ExtractOptions {
+        overwrite: OverwritePolicy::Backup,
+        ..ExtractOptions::default()
+    };
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    extract_model_info_with_options(&ports, &progress, temp_dir.path(), &options)?;
+    let stats = extract_model_info_with_options(&ports, &progress, temp_dir.path(), &options)?.stats;
+
+    let mut backups = fs::read_dir(&model_dir)?
+        .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<Vec<_>, _>>()?;
+    backups.retain(|name| name.starts_with("model_info.json.") && name.ends_with(".bak"));
+    backups.sort();
+
+    assert_eq!(fs::read_to_string(model_dir.join(MODEL_INFO_FILE_NAME))?, "\"new\"");
+    assert_eq!(backups.len(), 2);
+    let contents = backups
+        .iter()
+        .map(|name| fs::read_to_string(model_dir.join(name)))
+        .collect::<Result<Vec<_>, _>>()?;
+    assert!(contents.contains(&"\"old\"".to_string()));
+    assert_eq!(stats.backed_up, 1);
+
+    Ok(())
+}
+
+#[test]
+fn keeps_existing_file_when_entry_crc_does_not_match() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let model_dir = temp_dir.path().join("model");
+    fs::create_dir_all(&model_dir)?;
+
+    fs::write(model_dir.join("model.safetensors"), b"")?;
+    fs::write(model_dir.join(MODEL_INFO_FILE_NAME), "\"old\"")?;
+
+    let zip_path = model_dir.join("model.zip");
+    let file = fs::File::create(&zip_path)?;
+    let mut zip = zip::ZipWriter::new(file);
+    zip.start_file(
+        MODEL_INFO_FILE_NAME,
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored),
     )?;
+    zip.write_all(b"\"new\"")?;
+    zip.finish()?;
+
+    // 中央ディレクトリの CRC-32 を書き換えて、読み切った時点で検証に失敗させる
+    let mut bytes = fs::read(&zip_path)?;
+    let central = bytes
+        .windows(4)
+        .position(|window| window == b"PK\x01\x02")
+        .ok_or("central directory not found")?;
+    bytes[central + 16] ^= 0xff;
+    fs::write(&zip_path, bytes)?;
 
     let ports = FsPorts::new();
     let progress = NoProgressReporter::new();
     let stats = extract_model_info(&ports, &progress, temp_dir.path())?;
 
-    assert!(good_dir.join(MODEL_INFO_FILE_NAME).exists());
-    assert_eq!(stats.extracted, 1);
+    let mut names = fs::read_dir(&model_dir)?
+        .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<Vec<_>, _>>()?;
+    names.sort();
+
+    assert_eq!(fs::read_to_string(model_dir.join(MODEL_INFO_FILE_NAME))?, "\"old\"");
+    assert_eq!(names, vec!["model.safetensors", "model.zip", MODEL_INFO_FILE_NAME]);
+    assert_eq!(stats.extracted, 0);
+
+    Ok(())
+}
+
+#[test]
+fn keeps_outcomes_of_entries_extracted_before_a_later_entry_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let model_dir = temp_dir.path().join("model");
+    fs::create_dir_all(&model_dir)?;
+    fs::write(model_dir.join("model.safetensors"), b"")?;
+
+    let zip_path = model_dir.join("model.zip");
+    let mut zip = zip::ZipWriter::new(fs::File::create(&zip_path)?);
+    let stored =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    zip.start_file(MODEL_INFO_FILE_NAME, stored)?;
+    zip.write_all(b"{}")?;
+    zip.start_file("README.md", stored)?;
+    zip.write_all(b"readme")?;
+    zip.finish()?;
+
+    // 2 つ目のエントリの CRC-32 だけを壊す
+    let mut bytes = fs::read(&zip_path)?;
+    let central = bytes
+        .windows(4)
+        .enumerate()
+        .filter(|(_, window)| *window == b"PK\x01\x02")
+        .map(|(position, _)| position)
+        .nth(1)
+        .ok_or("second central directory record not found")?;
+    bytes[central + 16] ^= 0xff;
+    fs::write(&zip_path, bytes)?;
+
+    let options = ExtractOptions {
+        entries: EntryPatterns::new([MODEL_INFO_FILE_NAME, "README.md"])?,
+        ..ExtractOptions::default()
+    };
+    let ports = FsPorts::new();
+    let outcomes = ports.extract_zip_entry_if_exists(&zip_path, &options, &model_dir)?;
+    assert!(matches!(
+        outcomes.as_slice(),
+        [ZipEntryOutcome::Extracted { .. }, ZipEntryOutcome::InvalidZip(error)]
+            if error.entry.as_deref() == Some("README.md")
+    ));
+
+    let progress = NoProgressReporter::new();
+    let stats = extract_model_info_with_options(&ports, &progress, temp_dir.path(), &options)?.stats;
+    assert_eq!((stats.extracted, stats.invalid_zips), (1, 1));
+    assert_eq!(stats.extracted_by_entry.get(MODEL_INFO_FILE_NAME), Some(&1));
+    assert!(!model_dir.join("README.md").exists());
+
+    Ok(())
+}
+
+#[test]
+fn dry_run_plans_extraction_without_writing() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let new_dir = temp_dir.path().join("new");
+    let existing_dir = temp_dir.path().join("existing");
+    fs::create_dir_all(&new_dir)?;
+    fs::create_dir_all(&existing_dir)?;
+
+    fs::write(new_dir.join("model.safetensors"), b"")?;
+    create_zip(&new_dir.join("model.zip"), vec![(MODEL_INFO_FILE_NAME, "{}")])?;
+    create_zip(&new_dir.join("other.zip"), vec![("other.json", "{}")])?;
+    fs::write(existing_dir.join("model.safetensors"), b"")?;
+    fs::write(existing_dir.join(MODEL_INFO_FILE_NAME), "{}")?;
+    create_zip(
+        &existing_dir.join("model.zip"),
+        vec![(MODEL_INFO_FILE_NAME, "{}"), ("README.md", "readme")],
+    )?;
+    fs::write(existing_dir.join("README.md"), "old")?;
+
+    let options = ExtractOptions {
+        entries: EntryPatterns::new([MODEL_INFO_FILE_NAME, "README.md"])?,
+        overwrite: OverwritePolicy::IfDifferent,
+        header_fallback: true,
+        dry_run: true,
+        ..ExtractOptions::default()
+    };
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    let report = extract_model_info_with_options(&ports, &progress, temp_dir.path(), &options)?;
+    let plan = report.plan.ok_or("dry run must return a plan")?;
+
+    assert!(!new_dir.join(MODEL_INFO_FILE_NAME).exists());
+    assert_eq!(fs::read_to_string(existing_dir.join("README.md"))?, "old");
+    assert_eq!(report.stats.extracted, 0);
+
+    let archives = plan
+        .archives
+        .iter()
+        .map(|archive| {
+            let entries = archive
+                .entries
+                .iter()
+                .map(|entry| (entry.entry.as_str(), entry.action, entry.detail.as_deref()))
+                .collect::<Vec<_>>();
+            (archive.path.strip_prefix(temp_dir.path()).unwrap().to_path_buf(), entries)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        archives,
+        vec![
+            (
+                Path::new("existing/model.zip").to_path_buf(),
+                vec![
+                    (MODEL_INFO_FILE_NAME, PlannedAction::Keep, Some("unchanged")),
+                    ("README.md", PlannedAction::Replace, None),
+                ]
+            ),
+            (
+                Path::new("new/model.zip").to_path_buf(),
+                vec![(MODEL_INFO_FILE_NAME, PlannedAction::Create, None)]
+            ),
+            (Path::new("new/other.zip").to_path_buf(), vec![]),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn mirrors_outputs_under_output_root() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let source_root = temp_dir.path().join("library");
+    let output_root = temp_dir.path().join("mirror");
+    let model_dir = source_root.join("lora").join("style");
+    fs::create_dir_all(&model_dir)?;
+
+    create_safetensors(&model_dir.join("style.safetensors"), "{\"ss_output_name\": \"style\"}")?;
+    create_zip(
+        &model_dir.join("style.zip"),
+        vec![(MODEL_INFO_FILE_NAME, "{\"a\": 1}")],
+    )?;
+
+    let options = ExtractOptions {
+        output_root: Some(output_root.clone()),
+        training_report: true,
+        ..ExtractOptions::default()
+    };
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    let report = extract_model_info_with_options(&ports, &progress, &source_root, &options)?;
+
+    let mirrored_dir = output_root.join("lora").join("style");
+    assert_eq!(fs::read_to_string(mirrored_dir.join(MODEL_INFO_FILE_NAME))?, "{\"a\": 1}");
+    assert!(mirrored_dir.join("style.training.json").exists());
+    assert!(!model_dir.join(MODEL_INFO_FILE_NAME).exists());
+    assert!(!model_dir.join("style.training.json").exists());
+    assert_eq!(report.stats.extracted, 1);
+    assert_eq!(report.training[0].sidecar_path, mirrored_dir.join("style.training.json"));
+
+    Ok(())
+}
+
+#[test]
+fn header_fallback_keeps_existing_model_info_under_output_root() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let source_root = temp_dir.path().join("library");
+    let output_root = temp_dir.path().join("mirror");
+    let options = ExtractOptions {
+        output_root: Some(output_root.clone()),
+        overwrite: OverwritePolicy::Never,
+        header_fallback: true,
+        ..ExtractOptions::default()
+    };
+
+    // zip のエントリは既にあるので飛ばされ、zip の無い方は出力先の既存ファイルだけがある
+    for name in ["zipped", "plain"] {
+        let model_dir = source_root.join(name);
+        fs::create_dir_all(&model_dir)?;
+        create_safetensors(&model_dir.join("model.safetensors"), "{\"ss_output_name\": \"x\"}")?;
+        let mirrored_dir = output_root.join(name);
+        fs::create_dir_all(&mirrored_dir)?;
+        fs::write(mirrored_dir.join(MODEL_INFO_FILE_NAME), "{\"edited\": true}")?;
+    }
+    create_zip(
+        &source_root.join("zipped").join("model.zip"),
+        vec![(MODEL_INFO_FILE_NAME, "{\"a\": 1}")],
+    )?;
+
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    let stats = extract_model_info_with_options(&ports, &progress, &source_root, &options)?.stats;
+
+    for name in ["zipped", "plain"] {
+        let mirrored = output_root.join(name).join(MODEL_INFO_FILE_NAME);
+        assert_eq!(fs::read_to_string(mirrored)?, "{\"edited\": true}");
+    }
+    assert_eq!((stats.skipped_existing, stats.generated_from_header), (1, 0));
+
+    Ok(())
+}
+
+#[test]
+fn reports_output_write_failure_instead_of_invalid_zip() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let root = temp_dir.path().join("library");
+    let blocked_dir = root.join("blocked");
+    let other_dir = root.join("other");
+    fs::create_dir_all(&blocked_dir)?;
+    fs::create_dir_all(&other_dir)?;
+
+    for dir in [&blocked_dir, &other_dir] {
+        fs::write(dir.join("model.safetensors"), b"")?;
+        create_zip(&dir.join("model.zip"), vec![(MODEL_INFO_FILE_NAME, "{}")])?;
+    }
+    // 展開先と同じ名前のディレクトリがあると置き換えられず、書き込み側の失敗になる
+    fs::create_dir(blocked_dir.join(MODEL_INFO_FILE_NAME))?;
+
+    let ports = FsPorts::new();
+    let quiet = NoProgressReporter::new();
+    let progress = ErrorCollectingReporter::new(&quiet);
+    let stats = extract_model_info_with_options(&ports, &progress, &root, &ExtractOptions::default())?.stats;
+
+    // 一つの出力先に書けなくても、残りのディレクトリの処理は続ける
+    assert_eq!((stats.write_failures, stats.invalid_zips, stats.extracted), (1, 0, 1));
+    assert_eq!(stats.failures(), 1);
+    assert_eq!(fs::read_to_string(other_dir.join(MODEL_INFO_FILE_NAME))?, "{}");
+    let errors = progress.into_errors();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].kind, "write_failure");
+    assert_eq!(errors[0].path, blocked_dir.join(MODEL_INFO_FILE_NAME));
+
+    let options = ExtractOptions {
+        on_error: ErrorPolicy::FailFast,
+        ..ExtractOptions::default()
+    };
+    let err = extract_model_info_with_options(&ports, &quiet, &root, &options)
+        .expect_err("fail-fast must stop at the write failure");
+    assert!(err.to_string().starts_with("cannot write"), "{err}");
+    assert!(err.is_output());
+    assert!(matches!(&err, ExtractError::Write { path, .. } if path.starts_with(&blocked_dir)));
+
+    Ok(())
+}
+
+#[test]
+fn run_report_records_write_failures_and_cached_archives() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let root = temp_dir.path().join("library");
+    let blocked_dir = root.join("blocked");
+    let other_dir = root.join("other");
+    for dir in [&blocked_dir, &other_dir] {
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join("model.safetensors"), b"")?;
+        create_zip(&dir.join("model.zip"), vec![(MODEL_INFO_FILE_NAME, "{}")])?;
+    }
+    fs::create_dir(blocked_dir.join(MODEL_INFO_FILE_NAME))?;
+
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    let options = ExtractOptions::default();
+    let state_path = temp_dir.path().join("state.json");
+    let report_path = temp_dir.path().join("report.json");
+    // (ディレクトリ, outcome, 書き込み失敗の理由が付いているか)
+    type Summary = Vec<(String, String, bool)>;
+    let run = || -> Result<Summary, Box<dyn std::error::Error>> {
+        let cache = StateCache::open(&state_path, &options)?;
+        let report = RunReportWriter::new(&report_path);
+        let sinks: Vec<&dyn CatalogSink> = vec![&cache, &report];
+        extract_model_info_with_catalog(&ports, &progress, &root, &options, Some(&sinks))?;
+        cache.finish()?;
+        report.finish()?;
+
+        let records: Vec<serde_json::Value> =
+            serde_json::from_str(&fs::read_to_string(&report_path)?)?;
+        let mut summary = records
+            .iter()
+            .map(|record| {
+                (
+                    record["directory"].as_str().unwrap_or_default().to_string(),
+                    record["outcome"].as_str().unwrap_or_default().to_string(),
+                    record["reason"].as_str().is_some_and(|reason| reason.contains("cannot write")),
+                )
+            })
+            .collect::<Vec<_>>();
+        summary.sort();
+        Ok(summary)
+    };
+    let blocked = blocked_dir.to_string_lossy().into_owned();
+    let other = other_dir.to_string_lossy().into_owned();
+
+    // 書き込めなかったアーカイブも一件として残り、キャッシュはされない
+    assert_eq!(
+        run()?,
+        vec![
+            (blocked.clone(), "write_failed".to_string(), true),
+            (other.clone(), "extracted".to_string(), false),
+        ]
+    );
+    // キャッシュで開かなかったアーカイブも cached として残る
+    assert_eq!(
+        run()?,
+        vec![
+            (blocked, "write_failed".to_string(), true),
+            (other, "cached".to_string(), false),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn tries_passwords_on_encrypted_archives() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let root = temp_dir.path().join("models");
+    let model_dir = root.join("model");
+    fs::create_dir_all(&model_dir)?;
+
+    fs::write(model_dir.join("model.safetensors"), b"")?;
+    let contents = "{\"a\": 1}";
+    let header_check = (crc32(contents.as_bytes()) >> 24) as u8;
+    create_encrypted_zip(&model_dir.join("model.zip"), MODEL_INFO_FILE_NAME, contents, "secret")?;
+
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    let run = |passwords: &[&str]| {
+        let options = ExtractOptions {
+            passwords: ZipPasswords::new(passwords.iter().copied()),
+            ..ExtractOptions::default()
+        };
+        extract_model_info_with_options(&ports, &progress, &root, &options).map(|report| report.stats)
+    };
+
+    // パスワードが無い場合も合わない場合も、壊れたアーカイブとは別に数える
+    for passwords in [&[][..], &["wrong"][..]] {
+        let stats = run(passwords)?;
+        assert_eq!((stats.password_required, stats.invalid_zips, stats.extracted), (1, 0, 0));
+        assert!(!model_dir.join(MODEL_INFO_FILE_NAME).exists());
+    }
+
+    let stats = run(&["wrong", "secret"])?;
+    assert_eq!((stats.password_required, stats.extracted), (0, 1));
+    assert_eq!(fs::read_to_string(model_dir.join(MODEL_INFO_FILE_NAME))?, "{\"a\": 1}");
+
+    // 1 バイトの確認だけなら通ってしまう違うパスワードが先にあっても、読み切れる方を使う
+    let header = zipcrypto_header(contents, "secret");
+    let impostor = (0..)
+        .map(|n| format!("wrong{n}"))
+        .find(|candidate| {
+            let mut crypto = ZipCrypto::new(candidate);
+            header.iter().map(|byte| crypto.decrypt(*byte)).last() == Some(header_check)
+        })
+        .expect("some password passes the one-byte check");
+    fs::remove_file(model_dir.join(MODEL_INFO_FILE_NAME))?;
+    let stats = run(&[impostor.as_str()])?;
+    assert_eq!((stats.invalid_zips, stats.extracted), (1, 0));
+    let stats = run(&[impostor.as_str(), "secret"])?;
+    assert_eq!((stats.invalid_zips, stats.password_required, stats.extracted), (0, 0, 1));
+    assert_eq!(fs::read_to_string(model_dir.join(MODEL_INFO_FILE_NAME))?, contents);
+
+    fs::remove_file(model_dir.join(MODEL_INFO_FILE_NAME))?;
+    fs::write(model_dir.join(ZIP_PASSWORD_FILE_NAME), "old\r\nsecret\n")?;
+    let stats = run(&[])?;
+    assert_eq!((stats.password_required, stats.extracted), (0, 1));
+
+    Ok(())
+}
+
+#[test]
+fn reads_password_file_only_for_encrypted_archives() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let plain_dir = temp_dir.path().join("plain");
+    let locked_dir = temp_dir.path().join("locked");
+    for dir in [&plain_dir, &locked_dir] {
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join("model.safetensors"), b"")?;
+        // 読めないパスワードファイル
+        fs::create_dir_all(dir.join(ZIP_PASSWORD_FILE_NAME))?;
+    }
+    create_zip(&plain_dir.join("model.zip"), vec![(MODEL_INFO_FILE_NAME, "{}")])?;
+    let locked_zip = locked_dir.join("model.zip");
+    create_encrypted_zip(&locked_zip, MODEL_INFO_FILE_NAME, "{}", "secret")?;
+
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    let options = ExtractOptions::default();
+    let stats = extract_model_info_with_options(&ports, &progress, temp_dir.path(), &options)?.stats;
+    assert_eq!((stats.extracted, stats.password_required), (1, 1));
+    assert!(plain_dir.join(MODEL_INFO_FILE_NAME).exists());
+
+    let outcomes = ports.extract_zip_entry_if_exists(&locked_zip, &options, &locked_dir)?;
+    let [ZipEntryOutcome::PasswordRequired(error)] = outcomes.as_slice() else {
+        panic!("unexpected outcomes: {outcomes:?}");
+    };
+    assert!(error.to_string().contains(ZIP_PASSWORD_FILE_NAME));
+
+    Ok(())
+}
+
+#[test]
+fn classifies_invalid_archives_by_kind() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let corrupt = temp_dir.path().join("corrupt.zip");
+    fs::write(&corrupt, b"not a zip")?;
+
+    let ports = FsPorts::new();
+    let outcomes =
+        ports.extract_zip_entry_if_exists(&corrupt, &ExtractOptions::default(), temp_dir.path())?;
+
+    let [ZipEntryOutcome::InvalidZip(error)] = outcomes.as_slice() else {
+        panic!("unexpected outcomes: {outcomes:?}");
+    };
+    assert_eq!(error.kind, InvalidArchiveKind::Corrupt);
+    assert_eq!(error.path, corrupt);
+    assert!(error.to_string().starts_with("corrupt archive: "), "{error}");
+
+    Ok(())
+}
+
+#[test]
+fn writes_catalog_of_extracted_generated_and_existing_model_info() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let root = temp_dir.path().join("models");
+    let extracted_dir = root.join("extracted");
+    let existing_dir = root.join("existing");
+    let generated_dir = root.join("generated");
+    let plain_dir = root.join("plain");
+    for dir in [&extracted_dir, &existing_dir, &generated_dir, &plain_dir] {
+        fs::create_dir_all(dir)?;
+    }
+
+    fs::write(extracted_dir.join("b.safetensors"), b"1234")?;
+    fs::write(extracted_dir.join("a.safetensors"), b"12")?;
+    create_zip(
+        &extracted_dir.join("model.zip"),
+        vec![(MODEL_INFO_FILE_NAME, "{\"name\": \"extracted\"}")],
+    )?;
+    fs::write(existing_dir.join("model.safetensors"), b"")?;
+    fs::write(existing_dir.join(MODEL_INFO_FILE_NAME), "hand edited")?;
+    create_safetensors(&generated_dir.join("model.safetensors"), "{\"name\": \"generated\"}")?;
+    fs::write(plain_dir.join("model.safetensors"), b"")?;
+
+    let options = ExtractOptions {
+        header_fallback: true,
+        ..ExtractOptions::default()
+    };
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+
+    // 二回目の実行では一回目にヘッダーから生成したファイルが既存扱いになる
+    let runs = [("catalog.jsonl", "generated"), ("catalog.json", "existing")];
+    for (catalog_name, generated_source) in runs {
+        let catalog_path = temp_dir.path().join(catalog_name);
+        let catalog = CatalogWriter::create(&catalog_path)?;
+        extract_model_info_with_catalog(&ports, &progress, &root, &options, Some(&catalog))?;
+        assert_eq!(catalog.finish()?, 3);
+
+        let contents = fs::read_to_string(&catalog_path)?;
+        let mut records = if catalog_name.ends_with(".jsonl") {
+            contents
+                .lines()
+                .map(serde_json::from_str)
+                .collect::<Result<Vec<serde_json::Value>, _>>()?
+        } else {
+            serde_json::from_str::<Vec<serde_json::Value>>(&contents)?
+        };
+        records.sort_by_key(|record| record["directory"].as_str().map(str::to_string));
+
+        let summary = records
+            .iter()
+            .map(|record| (record["source"].clone(), record["model_info"].clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                (serde_json::json!("existing"), serde_json::Value::Null),
+                (serde_json::json!("extracted"), serde_json::json!({"name": "extracted"})),
+                (serde_json::json!(generated_source), serde_json::json!({"name": "generated"})),
+            ]
+        );
+        assert_eq!(
+            records[1]["models"],
+            serde_json::json!([
+                {"file_name": "a.safetensors", "size": 2},
+                {"file_name": "b.safetensors", "size": 4},
+            ])
+        );
+        assert_eq!(
+            records[1]["source_archive"],
+            serde_json::json!(extracted_dir.join("model.zip"))
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn writes_per_archive_run_report() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let root = temp_dir.path().join("models");
+    let model_dir = root.join("model");
+    fs::create_dir_all(&model_dir)?;
+
+    fs::write(model_dir.join("model.safetensors"), b"")?;
+    create_zip(&model_dir.join("a.zip"), vec![(MODEL_INFO_FILE_NAME, "{\"a\": 1}")])?;
+    create_zip(&model_dir.join("b.zip"), vec![("other.json", "{}")])?;
+    fs::write(model_dir.join("c.zip"), b"not a zip")?;
+
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    let json_path = temp_dir.path().join("report.json");
+    let csv_path = temp_dir.path().join("report.csv");
+    let json_report = RunReportWriter::new(&json_path);
+    let csv_report = RunReportWriter::new(&csv_path);
+    let sinks: Vec<&dyn CatalogSink> = vec![&json_report, &csv_report];
+    let options = ExtractOptions::default();
+    extract_model_info_with_catalog(&ports, &progress, &root, &options, Some(&sinks))?;
+    assert_eq!(json_report.finish()?, 3);
+    assert_eq!(csv_report.finish()?, 3);
+
+    let records: Vec<serde_json::Value> = serde_json::from_str(&fs::read_to_string(&json_path)?)?;
+    let summary = records
+        .iter()
+        .map(|record| {
+            (
+                record["outcome"].as_str().unwrap_or_default().to_string(),
+                record["bytes_written"].as_u64().unwrap_or_default(),
+                record["reason"].is_string(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        vec![
+            ("extracted".to_string(), 8, false),
+            ("not_found".to_string(), 0, false),
+            ("invalid".to_string(), 0, true),
+        ]
+    );
+    assert_eq!(records[0]["directory"], serde_json::json!(model_dir));
+
+    let csv = fs::read_to_string(&csv_path)?;
+    let mut lines = csv.lines();
+    assert_eq!(
+        lines.next(),
+        Some("path,directory,outcome,reason,bytes_written,duration_ms")
+    );
+    assert_eq!(lines.count(), 3);
+
+    Ok(())
+}
+
+#[test]
+fn state_cache_skips_unchanged_archives_until_target_is_missing() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let root = temp_dir.path().join("models");
+    let model_dir = root.join("model");
+    fs::create_dir_all(&model_dir)?;
+
+    fs::write(model_dir.join("model.safetensors"), b"")?;
+    create_zip(&model_dir.join("a.zip"), vec![(MODEL_INFO_FILE_NAME, "{\"a\": 1}")])?;
+    create_zip(&model_dir.join("b.zip"), vec![("other.json", "{}")])?;
+    fs::write(model_dir.join("c.zip"), b"not a zip")?;
+
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    let options = ExtractOptions::default();
+    let state_path = temp_dir.path().join("state.json");
+    let run = || -> Result<_, Box<dyn std::error::Error>> {
+        let cache = StateCache::open(&state_path, &options)?;
+        let stats =
+            extract_model_info_with_catalog(&ports, &progress, &root, &options, Some(&cache))?
+                .stats;
+        cache.finish()?;
+        Ok(stats)
+    };
+
+    let stats = run()?;
+    assert_eq!((stats.zip_files_checked, stats.skipped_cached), (3, 0));
+
+    // 壊れたアーカイブは覚えないので毎回報告される
+    let stats = run()?;
+    assert_eq!((stats.zip_files_checked, stats.skipped_cached), (1, 2));
+    assert_eq!(stats.invalid_zips, 1);
+
+    fs::remove_file(model_dir.join(MODEL_INFO_FILE_NAME))?;
+    let stats = run()?;
+    assert_eq!((stats.zip_files_checked, stats.skipped_cached), (2, 1));
+    assert_eq!(stats.extracted, 1);
+    assert!(model_dir.join(MODEL_INFO_FILE_NAME).exists());
+
+    create_zip(&model_dir.join("b.zip"), vec![(MODEL_INFO_FILE_NAME, "{\"b\": 2}"), ("x", "")])?;
+    let stats = run()?;
+    assert_eq!((stats.zip_files_checked, stats.skipped_cached), (2, 1));
+
+    let other_options = ExtractOptions {
+        entries: EntryPatterns::new(["*.json"])?,
+        ..ExtractOptions::default()
+    };
+    let cache = StateCache::open(&state_path, &other_options)?;
+    let stats =
+        extract_model_info_with_catalog(&ports, &progress, &root, &other_options, Some(&cache))?
+            .stats;
+    assert_eq!(stats.skipped_cached, 0);
+
+    Ok(())
+}
+
+#[test]
+fn state_cache_is_discarded_when_overwrite_policy_changes() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let model_dir = temp_dir.path().join("model");
+    fs::create_dir_all(&model_dir)?;
+
+    fs::write(model_dir.join("model.safetensors"), b"")?;
+    fs::write(model_dir.join(MODEL_INFO_FILE_NAME), "{\"old\": true}")?;
+    create_zip(&model_dir.join("model.zip"), vec![(MODEL_INFO_FILE_NAME, "{\"new\": true}")])?;
+
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    let state_path = temp_dir.path().join("state.json");
+    let run = |overwrite| -> Result<_, Box<dyn std::error::Error>> {
+        let options = ExtractOptions {
+            overwrite,
+            ..ExtractOptions::default()
+        };
+        let cache = StateCache::open(&state_path, &options)?;
+        let root = temp_dir.path();
+        let stats =
+            extract_model_info_with_catalog(&ports, &progress, root, &options, Some(&cache))?.stats;
+        cache.finish()?;
+        Ok(stats)
+    };
+
+    let stats = run(OverwritePolicy::Never)?;
+    assert_eq!((stats.skipped_existing, stats.skipped_cached), (1, 0));
+    let stats = run(OverwritePolicy::Never)?;
+    assert_eq!(stats.skipped_cached, 1);
+
+    // 「既にある」で残したアーカイブも、上書きする方針に変えたら展開し直す
+    let stats = run(OverwritePolicy::Always)?;
+    assert_eq!((stats.extracted, stats.skipped_cached), (1, 0));
+    assert_eq!(fs::read_to_string(model_dir.join(MODEL_INFO_FILE_NAME))?, "{\"new\": true}");
+
+    Ok(())
+}
+
+#[test]
+fn state_cache_is_saved_under_output_root_that_does_not_exist_yet() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let root = temp_dir.path().join("library");
+    fs::create_dir_all(&root)?;
+    let output_root = temp_dir.path().join("mirror");
+
+    // 何も展開しなかった実行でも、出力先に状態ファイルを置ける
+    let options = ExtractOptions {
+        output_root: Some(output_root.clone()),
+        ..ExtractOptions::default()
+    };
+    let state_path = StateCache::default_path(&output_root);
+    let cache = StateCache::open(&state_path, &options)?;
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    extract_model_info_with_catalog(&ports, &progress, &root, &options, Some(&cache))?;
+    cache.finish()?;
+    assert!(state_path.is_file());
+
+    Ok(())
+}
+
+#[test]
+fn processes_only_the_given_directories() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let changed_dir = temp_dir.path().join("changed");
+    let other_dir = temp_dir.path().join("other");
+
+    for dir in [&changed_dir, &other_dir] {
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join("model.safetensors"), b"")?;
+        create_zip(&dir.join("model.zip"), vec![(MODEL_INFO_FILE_NAME, "{}")])?;
+    }
+
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    let report = extract_model_info_in_directories(
+        &ports,
+        &progress,
+        temp_dir.path(),
+        std::slice::from_ref(&changed_dir),
+        &ExtractOptions::default(),
+        None,
+    )?;
+
+    assert_eq!(report.stats.directories_scanned, 1);
+    assert_eq!(report.stats.extracted, 1);
+    assert!(changed_dir.join(MODEL_INFO_FILE_NAME).exists());
+    assert!(!other_dir.join(MODEL_INFO_FILE_NAME).exists());
+
+    Ok(())
+}
+
+#[test]
+fn change_watcher_batches_changed_directories_after_debounce() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let root = temp_dir.path().canonicalize()?;
+    let first_dir = root.join("first");
+    let second_dir = root.join("second");
+    let ignored_dir = root.join("ignored");
+    let removed_dir = root.join("removed");
+    for dir in [&first_dir, &second_dir, &ignored_dir, &removed_dir] {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(removed_dir.join("model.safetensors"), b"")?;
+
+    let watcher = ChangeWatcher::new(&root, Duration::from_millis(500))?;
+    let is_relevant = |path: &Path| path.extension().is_some_and(|ext| ext == "safetensors");
+
+    // debounce の間に続けて置かれたファイルは、ディレクトリごとに一つのバッチにまとまる
+    let writer = {
+        let first_dir = first_dir.clone();
+        let second_dir = second_dir.clone();
+        let ignored_dir = ignored_dir.clone();
+        let removed_dir = removed_dir.clone();
+        std::thread::spawn(move || -> std::io::Result<()> {
+            fs::write(first_dir.join("model.safetensors"), b"")?;
+            fs::write(ignored_dir.join("notes.txt"), b"")?;
+            // 削除は Create / Modify ではないので対象にならない
+            fs::remove_file(removed_dir.join("model.safetensors"))?;
+            std::thread::sleep(Duration::from_millis(100));
+            fs::write(second_dir.join("model.safetensors"), b"")?;
+            Ok(())
+        })
+    };
+    let batch = watcher.next_batch(&is_relevant)?;
+    writer.join().map_err(|_| "writer thread panicked")??;
+    assert_eq!(batch.directories, vec![first_dir.clone(), second_dir]);
+    assert!(!batch.rescan);
+    assert!(batch.errors.is_empty());
+
+    // 静かになった後の変更は次のバッチになる
+    fs::write(first_dir.join("model.safetensors"), b"{}")?;
+    let batch = watcher.next_batch(&is_relevant)?;
+    assert_eq!(batch.directories, vec![first_dir]);
+
+    Ok(())
+}
+
+#[test]
+fn change_watcher_finds_model_files_in_moved_in_directory() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let base = temp_dir.path().canonicalize()?;
+    let root = base.join("library");
+    let staged = base.join("stage").join("lora");
+    let sentinel_dir = root.join("sentinel");
+    fs::create_dir_all(&sentinel_dir)?;
+    fs::create_dir_all(staged.join("nested"))?;
+    fs::write(staged.join("model.safetensors"), b"")?;
+    fs::write(staged.join("nested").join("model.safetensors"), b"")?;
+    fs::write(staged.join("notes.txt"), b"")?;
+
+    let watcher = ChangeWatcher::new(&root, Duration::from_millis(300))?;
+    let is_relevant = |path: &Path| path.extension().is_some_and(|ext| ext == "safetensors");
+
+    // 移動ではディレクトリのイベントが一つ来るだけなので、その下のモデルのあるディレクトリを全部処理し直す
+    fs::rename(&staged, root.join("lora"))?;
+    // 取りこぼしてもテストが待ち続けないよう、普通に置かれるファイルも一つ混ぜる
+    fs::write(sentinel_dir.join("model.safetensors"), b"")?;
+    let batch = watcher.next_batch(&is_relevant)?;
+    assert_eq!(
+        batch.directories,
+        vec![root.join("lora"), root.join("lora").join("nested"), sentinel_dir]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn prunes_excluded_and_not_included_subtrees() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let root = temp_dir.path();
+    let dirs = [
+        "loras/sdxl/a",
+        "loras/sdxl/.git/b",
+        "loras/old/c",
+        "checkpoints/d",
+        ".cache/e",
+    ];
+    for dir in dirs {
+        let dir = root.join(dir);
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("model.safetensors"), b"")?;
+        create_zip(&dir.join("model.zip"), vec![(MODEL_INFO_FILE_NAME, "{}")])?;
+    }
+    let extracted = || {
+        dirs.iter()
+            .filter(|dir| root.join(dir).join(MODEL_INFO_FILE_NAME).exists())
+            .map(|dir| dir.to_string())
+            .collect::<Vec<_>>()
+    };
+
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    let options = ExtractOptions {
+        directories: DirectoryFilter::new(["loras"], DEFAULT_EXCLUDES.iter().chain(&["**/old"]))?,
+        ..ExtractOptions::default()
+    };
+    let stats = extract_model_info_with_options(&ports, &progress, root, &options)?.stats;
+    assert_eq!(extracted(), vec!["loras/sdxl/a"]);
+    // 対象になるのは loras、loras/sdxl、loras/sdxl/a だけで、刈り込んだ枝の中には入らない
+    assert_eq!(stats.directories_scanned, 3);
+
+    let stats = extract_model_info(&ports, &progress, root)?;
+    assert_eq!(stats.extracted, 3);
+    assert_eq!(extracted(), vec!["loras/sdxl/a", "loras/old/c", "checkpoints/d"]);
+
+    Ok(())
+}
+
+// root で動かすテストでは権限エラーを作れないので、特定のディレクトリの一覧取得だけを失敗させる
+struct UnreadableDirectoryPorts {
+    inner: FsPorts,
+    unreadable: PathBuf,
+}
+
+impl FilePorts for UnreadableDirectoryPorts {
+    fn for_each_directory(
+        &self,
+        root: &Path,
+        filter: &DirectoryFilter,
+        on_dir: &mut dyn FnMut(PathBuf) -> Result<(), ExtractError>,
+        on_error: &mut dyn FnMut(PathBuf, ExtractError) -> Result<(), ExtractError>,
+    ) -> Result<(), ExtractError> {
+        self.inner.for_each_directory(root, filter, on_dir, on_error)
+    }
+
+    fn list_files_in_dir(&self, dir: &Path) -> Result<Vec<PathBuf>, ExtractError> {
+        if dir == self.unreadable {
+            return Err(std::io::Error::from(std::io::ErrorKind::PermissionDenied).into());
+        }
+        self.inner.list_files_in_dir(dir)
+    }
+
+    fn is_archive(&self, path: &Path) -> bool {
+        self.inner.is_archive(path)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.inner.is_file(path)
+    }
+
+    fn extract_zip_entry_if_exists(
+        &self,
+        zip_path: &Path,
+        options: &ExtractOptions,
+        output_dir: &Path,
+    ) -> Result<Vec<ZipEntryOutcome>, ExtractError> {
+        self.inner.extract_zip_entry_if_exists(zip_path, options, output_dir)
+    }
+
+    fn generate_model_info_from_header(
+        &self,
+        model_path: &Path,
+        output_dir: &Path,
+    ) -> Result<HeaderOutcome, ExtractError> {
+        self.inner.generate_model_info_from_header(model_path, output_dir)
+    }
+
+    fn read_model_metadata(&self, model_path: &Path) -> Result<HeaderMetadata, ExtractError> {
+        self.inner.read_model_metadata(model_path)
+    }
+
+    fn write_training_sidecar(
+        &self,
+        model_path: &Path,
+        output_dir: &Path,
+        metadata: &TrainingMetadata,
+    ) -> Result<PathBuf, ExtractError> {
+        self.inner.write_training_sidecar(model_path, output_dir, metadata)
+    }
+
+    fn write_gguf_metadata(
+        &self,
+        model_path: &Path,
+        output_dir: &Path,
+    ) -> Result<HeaderOutcome, ExtractError> {
+        self.inner.write_gguf_metadata(model_path, output_dir)
+    }
+
+    fn read_file(&self, path: &Path) -> Result<Vec<u8>, ExtractError> {
+        self.inner.read_file(path)
+    }
+
+    fn file_size(&self, path: &Path) -> Result<u64, ExtractError> {
+        self.inner.file_size(path)
+    }
+}
+
+#[test]
+fn error_policy_decides_whether_unreadable_directories_stop_the_run() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let locked_dir = temp_dir.path().join("locked");
+    let model_dir = temp_dir.path().join("model");
+    for dir in [&locked_dir, &model_dir] {
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join("model.safetensors"), b"")?;
+        create_zip(&dir.join("model.zip"), vec![(MODEL_INFO_FILE_NAME, "{}")])?;
+    }
+
+    let ports = UnreadableDirectoryPorts {
+        inner: FsPorts::new(),
+        unreadable: locked_dir.clone(),
+    };
+    let inner = NoProgressReporter::new();
+    let progress = ErrorCollectingReporter::new(&inner);
+    let options = ExtractOptions::default();
+    let stats =
+        extract_model_info_with_options(&ports, &progress, temp_dir.path(), &options)?.stats;
+
+    assert_eq!(stats.extracted, 1);
+    assert_eq!(stats.unreadable_directories, 1);
+    assert_eq!(stats.failures(), 1);
+    assert!(model_dir.join(MODEL_INFO_FILE_NAME).exists());
+    let errors = progress.into_errors();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].kind, "unreadable_directory");
+    assert_eq!(errors[0].path, locked_dir);
+
+    let options = ExtractOptions {
+        on_error: ErrorPolicy::FailFast,
+        ..ExtractOptions::default()
+    };
+    let result = extract_model_info_with_options(&ports, &inner, temp_dir.path(), &options);
+    assert!(matches!(result, Err(ExtractError::Io(_))));
+
+    Ok(())
+}
+
+#[test]
+fn extracts_from_nested_directories() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let nested_dir = temp_dir.path().join("a").join("b").join("c");
+    fs::create_dir_all(&nested_dir)?;
+
+    fs::write(nested_dir.join("model.safetensors"), b"")?;
+    create_zip(
+        &nested_dir.join("model.zip"),
+        vec![(MODEL_INFO_FILE_NAME, "\"nested\"")],
+    )?;
+
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    let stats = extract_model_info(&ports, &progress, temp_dir.path())?;
+
+    let extracted = fs::read_to_string(nested_dir.join(MODEL_INFO_FILE_NAME))?;
+    assert_eq!(extracted, "\"nested\"");
+    assert_eq!(stats.extracted, 1);
+
+    Ok(())
+}
+
+#[test]
+fn reports_stats_for_scanned_directories() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let dir_a = temp_dir.path().join("a");
+    let dir_b = temp_dir.path().join("b");
+
+    fs::create_dir_all(&dir_a)?;
+    fs::create_dir_all(&dir_b)?;
+    fs::write(dir_a.join("model.safetensors"), b"")?;
+    create_zip(
+        &dir_a.join("model.zip"),
+        vec![(MODEL_INFO_FILE_NAME, "\"ok\"")],
+    )?;
+
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    let stats = extract_model_info(&ports, &progress, temp_dir.path())?;
+
+    assert!(stats.directories_scanned >= 2);
+    assert_eq!(stats.model_directories, 1);
+    assert_eq!(stats.zip_files_checked, 1);
+    assert_eq!(stats.extracted, 1);
+
+    Ok(())
+}
+
+#[test]
+fn continues_when_zip_is_invalid() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let bad_dir = temp_dir.path().join("bad");
+    let good_dir = temp_dir.path().join("good");
+
+    fs::create_dir_all(&bad_dir)?;
+    fs::create_dir_all(&good_dir)?;
+
+    fs::write(bad_dir.join("model.safetensors"), b"")?;
+    fs::write(bad_dir.join("broken.zip"), b"not a zip")?;
+
+    fs::write(good_dir.join("model.safetensors"), b"")?;
+    create_zip(
+        &good_dir.join("model.zip"),
+        vec![(MODEL_INFO_FILE_NAME, "\"ok\"")],
+    )?;
+
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    let stats = extract_model_info(&ports, &progress, temp_dir.path())?;
+
+    assert!(good_dir.join(MODEL_INFO_FILE_NAME).exists());
+    assert_eq!(stats.extracted, 1);
+    assert_eq!(stats.invalid_zips, 1);
+    assert_eq!(stats.failures(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn extracts_every_entry_matching_configured_patterns() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let model_dir = temp_dir.path().join("model");
+    fs::create_dir_all(&model_dir)?;
+
+    fs::write(model_dir.join("model.safetensors"), b"")?;
+    create_zip(
+        &model_dir.join("model.zip"),
+        vec![
+            (MODEL_INFO_FILE_NAME, "{}"),
+            ("docs/README.md", "readme"),
+            ("preview.png", "png"),
+            ("preview.jpg", "jpg"),
+            ("other.txt", "other"),
+        ],
+    )?;
+
+    let options = ExtractOptions {
+        entries: EntryPatterns::new([MODEL_INFO_FILE_NAME, "README.md", "preview.*"])?,
+        ..ExtractOptions::default()
+    };
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    let stats = extract_model_info_with_options(&ports, &progress, temp_dir.path(), &options)?.stats;
+
+    assert!(model_dir.join(MODEL_INFO_FILE_NAME).exists());
+    assert_eq!(fs::read_to_string(model_dir.join("README.md"))?, "readme");
+    assert!(model_dir.join("preview.png").exists());
+    assert!(model_dir.join("preview.jpg").exists());
+    assert!(!model_dir.join("other.txt").exists());
+    assert_eq!(stats.extracted, 4);
+    assert_eq!(stats.extracted_by_entry.get(MODEL_INFO_FILE_NAME), Some(&1));
+    assert_eq!(stats.extracted_by_entry.get("README.md"), Some(&1));
+    assert_eq!(stats.extracted_by_entry.get("preview.*"), Some(&2));
+
+    Ok(())
+}
+
+#[test]
+fn rejects_invalid_entry_pattern() {
+    assert!(EntryPatterns::new(["[unclosed"]).is_err());
+}
+
+#[test]
+fn uses_configured_model_extensions_as_triggers() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let ckpt_dir = temp_dir.path().join("ckpt");
+    let gguf_dir = temp_dir.path().join("gguf");
+    let safe_dir = temp_dir.path().join("safe");
+    fs::create_dir_all(&ckpt_dir)?;
+    fs::create_dir_all(&gguf_dir)?;
+    fs::create_dir_all(&safe_dir)?;
+
+    fs::write(ckpt_dir.join("model.CKPT"), b"")?;
+    fs::write(ckpt_dir.join("model.pt"), b"")?;
+    create_zip(&ckpt_dir.join("model.zip"), vec![(MODEL_INFO_FILE_NAME, "\"ckpt\"")])?;
+    fs::write(gguf_dir.join("model.gguf"), b"")?;
+    create_zip(&gguf_dir.join("model.zip"), vec![(MODEL_INFO_FILE_NAME, "\"gguf\"")])?;
+    fs::write(safe_dir.join("model.safetensors"), b"")?;
+    create_zip(&safe_dir.join("model.zip"), vec![(MODEL_INFO_FILE_NAME, "\"safe\"")])?;
+
+    let options = ExtractOptions {
+        model_extensions: ModelExtensions::new(["ckpt", ".pt"]),
+        ..ExtractOptions::default()
+    };
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    let stats = extract_model_info_with_options(&ports, &progress, temp_dir.path(), &options)?.stats;
+
+    assert_eq!(fs::read_to_string(ckpt_dir.join(MODEL_INFO_FILE_NAME))?, "\"ckpt\"");
+    assert!(!gguf_dir.join(MODEL_INFO_FILE_NAME).exists());
+    assert!(!safe_dir.join(MODEL_INFO_FILE_NAME).exists());
+    assert_eq!(stats.model_directories, 1);
+    assert_eq!(stats.model_directories_by_extension.get("ckpt"), Some(&1));
+    assert_eq!(stats.model_directories_by_extension.get("pt"), Some(&1));
+    assert_eq!(stats.extracted, 1);
+
+    Ok(())
+}
+
+#[test]
+fn extracts_from_tar_family_archives() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let tar_dir = temp_dir.path().join("tar");
+    let tar_gz_dir = temp_dir.path().join("tar_gz");
+    let tar_zst_dir = temp_dir.path().join("tar_zst");
+    let gz_dir = temp_dir.path().join("gz");
+    for dir in [&tar_dir, &tar_gz_dir, &tar_zst_dir, &gz_dir] {
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join("model.safetensors"), b"")?;
+    }
+
+    create_tar(
+        fs::File::create(tar_dir.join("model.tar"))?,
+        vec![("bundle/model_info.json", "\"tar\"")],
+    )?;
+    create_tar(
+        flate2::write::GzEncoder::new(
+            fs::File::create(tar_gz_dir.join("model.tar.gz"))?,
+            flate2::Compression::default(),
+        ),
+        vec![(MODEL_INFO_FILE_NAME, "\"tar.gz\"")],
+    )?
+    .finish()?;
+    create_tar(
+        zstd::Encoder::new(fs::File::create(tar_zst_dir.join("model.tar.zst"))?, 0)?,
+        vec![(MODEL_INFO_FILE_NAME, "\"tar.zst\"")],
+    )?
+    .finish()?;
+    let mut encoder = flate2::write::GzEncoder::new(
+        fs::File::create(gz_dir.join("model_info.json.gz"))?,
+        flate2::Compression::default(),
+    );
+    encoder.write_all(b"\"gz\"")?;
+    encoder.finish()?;
+
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    let stats = extract_model_info(&ports, &progress, temp_dir.path())?;
+
+    assert_eq!(fs::read_to_string(tar_dir.join(MODEL_INFO_FILE_NAME))?, "\"tar\"");
+    assert_eq!(fs::read_to_string(tar_gz_dir.join(MODEL_INFO_FILE_NAME))?, "\"tar.gz\"");
+    assert_eq!(fs::read_to_string(tar_zst_dir.join(MODEL_INFO_FILE_NAME))?, "\"tar.zst\"");
+    assert_eq!(fs::read_to_string(gz_dir.join(MODEL_INFO_FILE_NAME))?, "\"gz\"");
+    assert_eq!(stats.zip_files_checked, 4);
+    assert_eq!(stats.extracted, 4);
+
+    Ok(())
+}
+
+#[test]
+fn continues_when_tar_gz_is_invalid() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let model_dir = temp_dir.path().join("model");
+    fs::create_dir_all(&model_dir)?;
+
+    fs::write(model_dir.join("model.safetensors"), b"")?;
+    fs::write(model_dir.join("broken.tar.gz"), b"not a tarball")?;
+
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    let stats = extract_model_info(&ports, &progress, temp_dir.path())?;
+
+    assert_eq!(stats.zip_files_checked, 1);
+    assert_eq!(stats.extracted, 0);
+    assert!(!model_dir.join(MODEL_INFO_FILE_NAME).exists());
+
+    Ok(())
+}
+
+#[test]
+fn generates_model_info_json_from_safetensors_header() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let header_dir = temp_dir.path().join("header");
+    let zip_dir = temp_dir.path().join("zip");
+    let existing_dir = temp_dir.path().join("existing");
+    fs::create_dir_all(&header_dir)?;
+    fs::create_dir_all(&zip_dir)?;
+    fs::create_dir_all(&existing_dir)?;
+
+    let metadata = r#"{"name": "lora", "ss_tag_frequency": "{\"tag\": 3}"}"#;
+    create_safetensors(&header_dir.join("model.safetensors"), metadata)?;
+    create_safetensors(&zip_dir.join("model.safetensors"), metadata)?;
+    create_zip(&zip_dir.join("model.zip"), vec![(MODEL_INFO_FILE_NAME, "\"from zip\"")])?;
+    create_safetensors(&existing_dir.join("model.safetensors"), metadata)?;
+    fs::write(existing_dir.join(MODEL_INFO_FILE_NAME), "hand edited")?;
+
+    let options = ExtractOptions {
+        header_fallback: true,
+        ..ExtractOptions::default()
+    };
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    let stats = extract_model_info_with_options(&ports, &progress, temp_dir.path(), &options)?.stats;
+
+    let generated: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(header_dir.join(MODEL_INFO_FILE_NAME))?)?;
+    assert_eq!(generated["name"], "lora");
+    assert_eq!(generated["ss_tag_frequency"]["tag"], 3);
+    assert_eq!(fs::read_to_string(zip_dir.join(MODEL_INFO_FILE_NAME))?, "\"from zip\"");
+    assert_eq!(fs::read_to_string(existing_dir.join(MODEL_INFO_FILE_NAME))?, "hand edited");
+    assert_eq!(stats.generated_from_header, 1);
+    assert_eq!(stats.extracted, 1);
+
+    Ok(())
+}
+
+#[test]
+fn header_fallback_is_opt_in_and_skips_invalid_headers() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let model_dir = temp_dir.path().join("model");
+    let broken_dir = temp_dir.path().join("broken");
+    fs::create_dir_all(&model_dir)?;
+    fs::create_dir_all(&broken_dir)?;
+
+    create_safetensors(&model_dir.join("model.safetensors"), r#"{"name": "lora"}"#)?;
+    fs::write(broken_dir.join("model.safetensors"), b"\xff\xff")?;
+
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    let stats = extract_model_info(&ports, &progress, temp_dir.path())?;
+    assert!(!model_dir.join(MODEL_INFO_FILE_NAME).exists());
+    assert_eq!(stats.generated_from_header, 0);
+
+    let options = ExtractOptions {
+        header_fallback: true,
+        ..ExtractOptions::default()
+    };
+    let stats = extract_model_info_with_options(&ports, &progress, temp_dir.path(), &options)?.stats;
+    assert!(model_dir.join(MODEL_INFO_FILE_NAME).exists());
+    assert!(!broken_dir.join(MODEL_INFO_FILE_NAME).exists());
+    assert_eq!(stats.generated_from_header, 1);
+    assert_eq!(stats.invalid_headers, 1);
+
+    Ok(())
+}
+
+#[test]
+fn counts_unwritable_generated_files_as_write_failures() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let blocked_dir = temp_dir.path().join("blocked");
+    let other_dir = temp_dir.path().join("other");
+    fs::create_dir_all(&blocked_dir)?;
+    fs::create_dir_all(&other_dir)?;
+
+    for dir in [&blocked_dir, &other_dir] {
+        create_safetensors(&dir.join("model.safetensors"), r#"{"name": "model"}"#)?;
+    }
+    fs::create_dir(blocked_dir.join(MODEL_INFO_FILE_NAME))?;
+
+    let options = ExtractOptions {
+        header_fallback: true,
+        ..ExtractOptions::default()
+    };
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    let stats = extract_model_info_with_options(&ports, &progress, temp_dir.path(), &options)?.stats;
+
+    // ヘッダから生成したファイルも、書けなければ報告して次のディレクトリに進む
+    assert_eq!((stats.write_failures, stats.generated_from_header), (1, 1));
+    assert!(other_dir.join(MODEL_INFO_FILE_NAME).is_file());
+
+    Ok(())
+}
+
+#[test]
+fn writes_training_sidecars_and_summary() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let lora_dir = temp_dir.path().join("lora");
+    let plain_dir = temp_dir.path().join("plain");
+    fs::create_dir_all(&lora_dir)?;
+    fs::create_dir_all(&plain_dir)?;
+
+    let metadata = r#"{
+        "ss_output_name": "style",
+        "ss_sd_model_name": "sd_xl_base_1.0.safetensors",
+        "ss_new_sd_model_hash": "abc123",
+        "ss_network_module": "networks.lora",
+        "ss_network_dim": "32",
+        "ss_network_alpha": "16.0",
+        "ss_num_epochs": "10",
+        "ss_steps": "1200",
+        "ss_tag_frequency": "{\"1_a\": {\"cat\": 5, \"dog\": 1}, \"2_b\": {\"cat\": 2}}"
+    }"#;
+    create_safetensors(&lora_dir.join("style.safetensors"), metadata)?;
+    create_safetensors(&plain_dir.join("plain.safetensors"), r#"{"name": "plain"}"#)?;
+
+    let options = ExtractOptions {
+        training_report: true,
+        ..ExtractOptions::default()
+    };
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    let report = extract_model_info_with_options(&ports, &progress, temp_dir.path(), &options)?;
+
+    assert_eq!(report.stats.training_reports, 1);
+    assert_eq!(report.training.len(), 1);
+    let record = &report.training[0];
+    assert_eq!(record.metadata.base_model_hash.as_deref(), Some("abc123"));
+    assert_eq!(record.metadata.network_dim, Some(32));
+    assert_eq!(record.metadata.network_alpha, Some(16.0));
+    assert_eq!(record.metadata.num_epochs, Some(10));
+    assert_eq!(record.metadata.tag_frequency.get("cat"), Some(&7));
+
+    let sidecar: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(lora_dir.join("style.training.json"))?)?;
+    assert_eq!(sidecar["network_module"], "networks.lora");
+    assert!(!plain_dir.join("plain.training.json").exists());
+
+    let csv_path = temp_dir.path().join("summary.csv");
+    write_training_summary(&csv_path, &report.training)?;
+    let csv = fs::read_to_string(&csv_path)?;
+    assert!(csv.starts_with("model_path,output_name,base_model_name"));
+    assert!(csv.contains("cat:7;dog:1"));
+
+    let json_path = temp_dir.path().join("summary.json");
+    write_training_summary(&json_path, &report.training)?;
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&json_path)?)?;
+    assert_eq!(json[0]["metadata"]["steps"], 1200);
+
+    Ok(())
+}
+
+#[test]
+fn writes_gguf_metadata_sidecars() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let llm_dir = temp_dir.path().join("llm");
+    fs::create_dir_all(&llm_dir)?;
+
+    create_gguf(&llm_dir.join("model.Q4_K_M.gguf"), 100)?;
+    fs::write(llm_dir.join("broken.gguf"), b"GGML")?;
+
+    let options = ExtractOptions {
+        gguf_metadata: true,
+        ..ExtractOptions::default()
+    };
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    let stats = extract_model_info_with_options(&ports, &progress, temp_dir.path(), &options)?.stats;
+
+    let sidecar: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(llm_dir.join("model.Q4_K_M.gguf.json"))?)?;
+    assert_eq!(sidecar["version"], 3);
+    assert_eq!(sidecar["architecture"], "llama");
+    assert_eq!(sidecar["context_length"], 4096);
+    assert_eq!(sidecar["quantization"], "Q4_K_M");
+    assert_eq!(sidecar["tokenizer"]["model"], "llama");
+    assert_eq!(sidecar["tokenizer"]["vocab_size"], 100);
+    assert_eq!(sidecar["metadata"]["tokenizer.ggml.tokens"]["length"], 100);
+    assert!(!llm_dir.join("broken.gguf.json").exists());
+    assert_eq!(stats.gguf_metadata, 1);
+
+    Ok(())
+}
+
+#[test]
+fn keeps_existing_model_info_json_when_entry_is_not_valid_json() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let model_dir = temp_dir.path().join("model");
+    fs::create_dir_all(&model_dir)?;
+
+    fs::write(model_dir.join("model.safetensors"), b"")?;
+    fs::write(model_dir.join(MODEL_INFO_FILE_NAME), "{\"good\": true}")?;
+    create_zip(
+        &model_dir.join("model.zip"),
+        vec![(MODEL_INFO_FILE_NAME, "{\"truncated\": "), ("README.md", "not json")],
+    )?;
+
+    let options = ExtractOptions {
+        entries: EntryPatterns::new([MODEL_INFO_FILE_NAME, "README.md"])?,
+        ..ExtractOptions::default()
+    };
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    let stats = extract_model_info_with_options(&ports, &progress, temp_dir.path(), &options)?.stats;
+
+    let existing = fs::read_to_string(model_dir.join(MODEL_INFO_FILE_NAME))?;
+    assert_eq!(existing, "{\"good\": true}");
+    assert_eq!(fs::read_to_string(model_dir.join("README.md"))?, "not json");
+    assert_eq!(stats.invalid_json, 1);
+    assert_eq!(stats.extracted, 1);
+
+    Ok(())
+}
+
+#[test]
+fn stops_extracting_entries_over_the_size_limits() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let zip_dir = temp_dir.path().join("zipped");
+    let gz_dir = temp_dir.path().join("gzipped");
+    fs::create_dir_all(&zip_dir)?;
+    fs::create_dir_all(&gz_dir)?;
+
+    // 2 MiB の空白はほとんど圧縮後の大きさを持たず、既定の圧縮率の制限を超える
+    let bomb = format!("{{\"a\": \"{}\"}}", " ".repeat(2 * 1024 * 1024));
+    fs::write(zip_dir.join("model.safetensors"), b"")?;
+    fs::write(zip_dir.join(MODEL_INFO_FILE_NAME), "{\"good\": true}")?;
+    create_zip(&zip_dir.join("model.zip"), vec![(MODEL_INFO_FILE_NAME, &bomb)])?;
+    // 単体の .gz は展開後の大きさを宣言しないので、読みながら止める
+    fs::write(gz_dir.join("model.safetensors"), b"")?;
+    let mut encoder = flate2::write::GzEncoder::new(
+        fs::File::create(gz_dir.join(format!("{MODEL_INFO_FILE_NAME}.gz")))?,
+        flate2::Compression::default(),
+    );
+    encoder.write_all(bomb.as_bytes())?;
+    encoder.finish()?;
+
+    let ports = FsPorts::new();
+    let quiet = NoProgressReporter::new();
+    let progress = ErrorCollectingReporter::new(&quiet);
+    let options = ExtractOptions::default();
+    let stats = extract_model_info_with_options(&ports, &progress, temp_dir.path(), &options)?.stats;
+
+    assert_eq!((stats.entries_too_large, stats.extracted), (2, 0));
+    assert_eq!(fs::read_to_string(zip_dir.join(MODEL_INFO_FILE_NAME))?, "{\"good\": true}");
+    assert!(!gz_dir.join(MODEL_INFO_FILE_NAME).exists());
+    // 途中まで書いた一時ファイルも残さない
+    for dir in [&zip_dir, &gz_dir] {
+        assert!(fs::read_dir(dir)?.all(|entry| {
+            entry.is_ok_and(|entry| !entry.file_name().to_string_lossy().ends_with(".tmp"))
+        }));
+    }
+    let errors = progress.into_errors();
+    assert!(errors.iter().all(|error| error.kind == "entry_too_large"));
+    assert!(errors.iter().any(|error| error.message.contains("expands more than 100x")));
+
+    let options = ExtractOptions {
+        limits: EntryLimits::unlimited(),
+        ..ExtractOptions::default()
+    };
+    let stats = extract_model_info_with_options(&ports, &quiet, temp_dir.path(), &options)?.stats;
+    assert_eq!((stats.entries_too_large, stats.extracted), (0, 2));
+
+    let options = ExtractOptions {
+        limits: EntryLimits {
+            max_size: Some(1024),
+            max_ratio: None,
+        },
+        ..ExtractOptions::default()
+    };
+    let stats = extract_model_info_with_options(&ports, &quiet, temp_dir.path(), &options)?.stats;
+    assert_eq!((stats.entries_too_large, stats.extracted), (2, 0));
+    assert_eq!(fs::read_to_string(gz_dir.join(MODEL_INFO_FILE_NAME))?, bomb);
+
+    Ok(())
+}
+
+#[test]
+fn validates_model_info_json_against_schema() -> Result<(), Box<dyn std::error::Error>> {
+    let temp_dir = tempfile::tempdir()?;
+    let schema_path = temp_dir.path().join("schema.json");
+    fs::write(
+        &schema_path,
+        r#"{
+            "type": "object",
+            "required": ["name", "baseModel", "trainedWords"],
+            "properties": {"trainedWords": {"type": "array", "items": {"type": "string"}}}
+        }"#,
+    )?;
+
+    let library = temp_dir.path().join("library");
+    let good_dir = library.join("good");
+    let bad_dir = library.join("bad");
+    let existing_dir = library.join("existing");
+    for dir in [&good_dir, &bad_dir, &existing_dir] {
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join("model.safetensors"), b"")?;
+    }
+    create_zip(
+        &good_dir.join("model.zip"),
+        vec![(MODEL_INFO_FILE_NAME, r#"{"name": "a", "baseModel": "SDXL", "trainedWords": []}"#)],
+    )?;
+    create_zip(
+        &bad_dir.join("model.zip"),
+        vec![(MODEL_INFO_FILE_NAME, r#"{"name": "b", "trainedWords": [1]}"#)],
+    )?;
+    fs::write(existing_dir.join(MODEL_INFO_FILE_NAME), r#"{"name": "c"}"#)?;
+
+    let mut options = ExtractOptions {
+        schema: Some(load_schema(&schema_path)?),
+        ..ExtractOptions::default()
+    };
+    let ports = FsPorts::new();
+    let progress = NoProgressReporter::new();
+    let stats = extract_model_info_with_options(&ports, &progress, &library, &options)?.stats;
+    assert_eq!(stats.extracted, 2);
+    assert_eq!(stats.schema_violations, 1);
+
+    options.validate_existing = true;
+    let stats = extract_model_info_with_options(&ports, &progress, &library, &options)?.stats;
+    assert_eq!(stats.schema_violations, 2);
+
+    let schema = options.schema.as_ref().unwrap();
+    let violations = schema.validate(&serde_json::from_str(r#"{"name": "b", "trainedWords": [1]}"#)?);
+    let pointers = violations.iter().map(|v| v.pointer.as_str()).collect::<Vec<_>>();
+    assert!(pointers.contains(&""));
+    assert!(pointers.contains(&"/trainedWords/0"));
 
     Ok(())
 }
@@ -4,8 +4,14 @@ use std::sync::Arc;
 use std::thread;
 
 use indicatif::ProgressDrawTarget;
-use extract_model_info_json::{ExtractStats, LineProgressReporter, ProgressReporter};
+use extract_model_info_json::{ExtractStats, LineProgressReporter, ProgressReporter, SchemaViolation};
 use extract_model_info_json::IndicatifProgressReporter;
+use extract_model_info_json::{ErrorCollectingReporter, ReportedError};
+use extract_model_info_json::{InvalidArchive, InvalidArchiveKind};
+
+fn corrupt(path: &str, message: &str) -> InvalidArchive {
+    InvalidArchive::new(Path::new(path), InvalidArchiveKind::Corrupt, message.to_string())
+}
 
 #[test]
 fn line_progress_reporter_writes_updates() {
@@ -16,9 +22,10 @@ fn line_progress_reporter_writes_updates() {
 
     let stats = ExtractStats {
         directories_scanned: 2,
-        safetensors_directories: 1,
+        model_directories: 1,
         zip_files_checked: 1,
         extracted: 1,
+        ..ExtractStats::default()
     };
 
     reporter.on_update(&stats);
@@ -35,10 +42,64 @@ fn line_progress_reporter_reports_invalid_zip() {
     let reporter = LineProgressReporter::with_writer(writer);
 
     reporter.on_start(Path::new("/tmp"));
-    reporter.on_invalid_zip(Path::new("/tmp/bad.zip"), "invalid");
+    reporter.on_invalid_zip(Path::new("/tmp/bad.zip"), &corrupt("/tmp/bad.zip", "invalid"));
+
+    let output = String::from_utf8(reporter.into_inner().into_inner()).unwrap();
+    assert!(output.contains("invalid zip: /tmp/bad.zip (corrupt archive: invalid)"));
+}
+
+#[test]
+fn line_progress_reporter_reports_invalid_header() {
+    let writer = Cursor::new(Vec::new());
+    let reporter = LineProgressReporter::with_writer(writer);
+
+    reporter.on_start(Path::new("/tmp"));
+    reporter.on_invalid_header(Path::new("/tmp/model.safetensors"), "truncated header");
+
+    let output = String::from_utf8(reporter.into_inner().into_inner()).unwrap();
+    assert!(output.contains("invalid header: /tmp/model.safetensors (truncated header)"));
+}
+
+#[test]
+fn line_progress_reporter_reports_invalid_json() {
+    let writer = Cursor::new(Vec::new());
+    let reporter = LineProgressReporter::with_writer(writer);
+
+    reporter.on_start(Path::new("/tmp"));
+    reporter.on_invalid_json(
+        Path::new("/tmp/model.zip"),
+        Path::new("/tmp/model_info.json"),
+        "EOF while parsing",
+    );
+
+    let output = String::from_utf8(reporter.into_inner().into_inner()).unwrap();
+    assert!(output.contains("invalid json: /tmp/model_info.json from /tmp/model.zip"));
+}
+
+#[test]
+fn line_progress_reporter_reports_schema_violations_with_pointers() {
+    let writer = Cursor::new(Vec::new());
+    let reporter = LineProgressReporter::with_writer(writer);
+
+    reporter.on_start(Path::new("/tmp"));
+    reporter.on_schema_violation(
+        Path::new("/tmp/model_info.json"),
+        &[
+            SchemaViolation {
+                pointer: String::new(),
+                message: "\"baseModel\" is a required property".to_string(),
+            },
+            SchemaViolation {
+                pointer: "/trainedWords/0".to_string(),
+                message: "1 is not of type \"string\"".to_string(),
+            },
+        ],
+    );
 
     let output = String::from_utf8(reporter.into_inner().into_inner()).unwrap();
-    assert!(output.contains("invalid zip: /tmp/bad.zip"));
+    assert!(output.contains("schema violation: /tmp/model_info.json"));
+    assert!(output.contains("  /: \"baseModel\" is a required property"));
+    assert!(output.contains("  /trainedWords/0: 1 is not of type"));
 }
 
 #[test]
@@ -49,11 +110,12 @@ fn line_progress_reporter_reports_invalid_zip_on_new_line_after_update() {
     reporter.on_start(Path::new("/tmp"));
     reporter.on_update(&ExtractStats {
         directories_scanned: 1,
-        safetensors_directories: 1,
+        model_directories: 1,
         zip_files_checked: 1,
         extracted: 0,
+        ..ExtractStats::default()
     });
-    reporter.on_invalid_zip(Path::new("/tmp/bad.zip"), "invalid");
+    reporter.on_invalid_zip(Path::new("/tmp/bad.zip"), &corrupt("/tmp/bad.zip", "invalid"));
 
     let output = String::from_utf8(reporter.into_inner().into_inner()).unwrap();
     assert!(output.contains("\ninvalid zip: /tmp/bad.zip"));
@@ -70,11 +132,12 @@ fn line_progress_reporter_handles_concurrent_updates() {
         handles.push(thread::spawn(move || {
             reporter.on_update(&ExtractStats {
                 directories_scanned: index + 1,
-                safetensors_directories: 0,
+                model_directories: 0,
                 zip_files_checked: 0,
                 extracted: 0,
+                ..ExtractStats::default()
             });
-            reporter.on_invalid_zip(Path::new("/tmp/bad.zip"), "invalid");
+            reporter.on_invalid_zip(Path::new("/tmp/bad.zip"), &corrupt("/tmp/bad.zip", "invalid"));
         }));
     }
 
@@ -84,9 +147,10 @@ fn line_progress_reporter_handles_concurrent_updates() {
 
     reporter.on_finish(&ExtractStats {
         directories_scanned: 8,
-        safetensors_directories: 0,
+        model_directories: 0,
         zip_files_checked: 0,
         extracted: 0,
+        ..ExtractStats::default()
     });
 
     let reporter = match Arc::try_unwrap(reporter) {
@@ -94,7 +158,7 @@ fn line_progress_reporter_handles_concurrent_updates() {
         Err(_) => panic!("reporter still shared"),
     };
     let output = String::from_utf8(reporter.into_inner().into_inner()).unwrap();
-    assert!(output.contains("invalid zip: /tmp/bad.zip"));
+    assert!(output.contains("invalid zip: /tmp/bad.zip (corrupt archive: invalid)"));
 }
 
 #[test]
@@ -105,12 +169,64 @@ fn indicatif_progress_reporter_runs_with_hidden_target() {
 
     let stats = ExtractStats {
         directories_scanned: 1,
-        safetensors_directories: 1,
+        model_directories: 1,
         zip_files_checked: 1,
         extracted: 0,
+        ..ExtractStats::default()
     };
 
     reporter.on_update(&stats);
-    reporter.on_invalid_zip(Path::new("/tmp/bad.zip"), "invalid");
+    reporter.on_invalid_zip(Path::new("/tmp/bad.zip"), &corrupt("/tmp/bad.zip", "invalid"));
     reporter.on_finish(&stats);
 }
+
+#[test]
+fn error_collecting_reporter_keeps_failures_for_the_summary() {
+    let inner = LineProgressReporter::with_writer(Cursor::new(Vec::new()));
+    let reporter = ErrorCollectingReporter::new(&inner);
+
+    reporter.on_invalid_zip(Path::new("a.zip"), &corrupt("a.zip", "broken"));
+    reporter.on_update(&ExtractStats {
+        extracted: 1,
+        ..ExtractStats::default()
+    });
+    reporter.on_invalid_json(Path::new("b.zip"), Path::new("model_info.json"), "eof");
+
+    assert_eq!(
+        reporter.into_errors(),
+        vec![
+            ReportedError {
+                kind: "invalid_zip".to_string(),
+                path: Path::new("a.zip").to_path_buf(),
+                message: "corrupt archive: broken".to_string(),
+            },
+            ReportedError {
+                kind: "invalid_json".to_string(),
+                path: Path::new("model_info.json").to_path_buf(),
+                message: "from b.zip: eof".to_string(),
+            },
+        ]
+    );
+    let output = String::from_utf8(inner.into_inner().into_inner()).unwrap();
+    assert!(output.contains("invalid zip: a.zip (corrupt archive: broken)"));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn extract_stats_serializes_with_field_names() {
+    use extract_model_info_json::NoProgressReporter;
+
+    let reporter = NoProgressReporter::new();
+    let collector = ErrorCollectingReporter::new(&reporter);
+    collector.on_finish(&ExtractStats::default());
+
+    let stats = ExtractStats {
+        extracted: 2,
+        ..ExtractStats::default()
+    };
+    let value = serde_json::to_value(&stats).unwrap();
+
+    assert_eq!(value["extracted"], 2);
+    assert_eq!(value["extracted_by_entry"], serde_json::json!({}));
+    assert!(collector.into_errors().is_empty());
+}